@@ -1,7 +1,10 @@
 // examples/basic_usage.rs
 
 use async_anthropic::{
-    types::{CreateMessagesRequestBuilder, MessageBuilder, MessageRole},
+    stream::StreamAccumulator,
+    types::{
+        CreateMessagesRequestBuilder, MessageBuilder, MessageRole, ToolBuilder, ToolDefinition,
+    },
     Client,
 };
 use serde_json::json;
@@ -18,34 +21,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .content("What is the weather like in San Francisco?")
             .build()
             .unwrap()])
-        .tools([json!({
-          "name": "get_weather",
-          "description": "Get the current weather in a given location",
-          "input_schema": {
-            "type": "object",
-            "properties": {
-              "location": {
-                "type": "string",
-                "description": "The city and state, e.g. San Francisco, CA"
-              }
-            },
-            "required": ["location"]
-          }
-        })
-        .as_object()
-        .unwrap()
-        .to_owned()])
+        .tools(vec![weather_tool()])
         .build()
         .unwrap();
 
     let mut stream = client.messages().create_stream(request).await;
+    let mut accumulator = StreamAccumulator::new();
 
     while let Some(response) = stream.next().await {
         match response {
-            Ok(msg) => println!("{msg:?}"),
+            Ok(msg) => {
+                println!("{msg:?}");
+                accumulator.push(&msg)?;
+            }
             Err(e) => eprintln!("Error: {e:?}"),
         }
     }
 
+    // The accumulated response has the tool input fully parsed
+    let response = accumulator.finish()?;
+    for tool_use in response.message().tool_uses() {
+        println!("Tool use: {tool_use:?}");
+    }
+
     Ok(())
 }
+
+fn weather_tool() -> ToolDefinition {
+    ToolBuilder::default()
+        .name("get_weather")
+        .description("Get the current weather in a given location")
+        .input_schema(json!({
+          "type": "object",
+          "properties": {
+            "location": {
+              "type": "string",
+              "description": "The city and state, e.g. San Francisco, CA"
+            }
+          },
+          "required": ["location"]
+        }))
+        .build()
+        .unwrap()
+        .into()
+}
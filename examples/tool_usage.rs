@@ -1,6 +1,7 @@
 use async_anthropic::{
     types::{
-        CreateMessagesRequestBuilder, MessageBuilder, MessageRole, ToolChoice, ToolResultBuilder,
+        CreateMessagesRequestBuilder, MessageBuilder, MessageRole, ToolBuilder, ToolChoice,
+        ToolDefinition, ToolResultBuilder,
     },
     Client,
 };
@@ -23,24 +24,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let request = CreateMessagesRequestBuilder::default()
         .model("claude-3-5-sonnet-20241022")
         .messages(messages.as_slice())
-        // TODO: Type the tool spec so we can skip the shenanigans
-        .tools([json!({
-          "name": "get_weather",
-          "description": "Get the current weather in a given location",
-          "input_schema": {
-            "type": "object",
-            "properties": {
-              "location": {
-                "type": "string",
-                "description": "The city and state, e.g. San Francisco, CA"
-              }
-            },
-            "required": ["location"]
-          }
-        })
-        .as_object()
-        .unwrap()
-        .to_owned()])
+        .tools(vec![weather_tool()])
         .tool_choice(ToolChoice::Auto)
         .build()
         .unwrap();
@@ -77,23 +61,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let request = CreateMessagesRequestBuilder::default()
         .model("claude-3-5-sonnet-20241022")
         .messages(messages.as_slice())
-        .tools([json!({
-          "name": "get_weather",
-          "description": "Get the current weather in a given location",
-          "input_schema": {
-            "type": "object",
-            "properties": {
-              "location": {
-                "type": "string",
-                "description": "The city and state, e.g. San Francisco, CA"
-              }
-            },
-            "required": ["location"]
-          }
-        })
-        .as_object()
-        .unwrap()
-        .to_owned()])
+        .tools(vec![weather_tool()])
         .build()
         .unwrap();
 
@@ -103,6 +71,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("{response:?}");
 
     // 2. ---
-    // CreateMessagesResponse { id: Some("msg_019EVre2rdkCFwusZpGPgzDp"), content: Some([Text(Text { text: "According to the weather report, it's pretty warm in San Francisco right now." })]), model: Some("claude-3-5-sonnet-20241022"), stop_reason: Some("end_turn"), stop_sequence: None, usage: Some(Usage { input_tokens: Some(516), output_tokens: Some(20) }) }
+    // CreateMessagesResponse { id: Some("msg_019EVre2rdkCFwusZpGPgzDp"), content: Some([Text(Text { text: "According to the weather report, it's pretty warm in San Francisco right now." })]), model: Some("claude-3-5-sonnet-20241022"), stop_reason: Some(EndTurn), stop_sequence: None, usage: Some(Usage { input_tokens: Some(516), output_tokens: Some(20) }) }
     Ok(())
 }
+
+fn weather_tool() -> ToolDefinition {
+    ToolBuilder::default()
+        .name("get_weather")
+        .description("Get the current weather in a given location")
+        .input_schema(json!({
+          "type": "object",
+          "properties": {
+            "location": {
+              "type": "string",
+              "description": "The city and state, e.g. San Francisco, CA"
+            }
+          },
+          "required": ["location"]
+        }))
+        .build()
+        .unwrap()
+        .into()
+}
@@ -0,0 +1,329 @@
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+use backoff::{backoff::Backoff as _, ExponentialBackoff, ExponentialBackoffBuilder};
+use futures::{Stream, StreamExt as _, TryStreamExt as _};
+
+use crate::{
+    errors::AnthropicError,
+    pagination::{paginate, PaginatedStream},
+    types::{
+        BatchProcessingStatus, BatchRequest, BatchResultKind, BatchResultStream,
+        CreateMessageBatchRequest, CreateMessagesRequest, DeleteMessageBatchResponse,
+        ListMessageBatchesResponse, ListParams, MessageBatch,
+    },
+    Client,
+};
+
+/// Maximum number of requests in a single batch
+pub const MAX_BATCH_SIZE: usize = 100_000;
+/// Maximum size of a single batch request
+pub const MAX_BATCH_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Batches<'c> {
+    client: &'c Client,
+}
+
+impl<'c> Batches<'c> {
+    pub fn new(client: &Client) -> Batches<'_> {
+        Batches { client }
+    }
+
+    /// Submits a batch of message requests for asynchronous processing
+    #[tracing::instrument(skip_all)]
+    pub async fn create(
+        &self,
+        request: impl Into<CreateMessageBatchRequest>,
+    ) -> Result<MessageBatch, AnthropicError> {
+        self.client
+            .post("/v1/messages/batches", request.into())
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get(&self, batch_id: impl AsRef<str>) -> Result<MessageBatch, AnthropicError> {
+        self.client
+            .get(&format!("/v1/messages/batches/{}", batch_id.as_ref()))
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list(&self) -> Result<ListMessageBatchesResponse, AnthropicError> {
+        self.list_page(&ListParams::default()).await
+    }
+
+    /// Lists a single page of batches, most recent first
+    #[tracing::instrument(skip_all)]
+    pub async fn list_page(
+        &self,
+        params: &ListParams,
+    ) -> Result<ListMessageBatchesResponse, AnthropicError> {
+        self.client
+            .get(&params.to_path("/v1/messages/batches"))
+            .await
+    }
+
+    /// Streams all batches from the position in `params`, fetching pages of `params.limit` as needed
+    ///
+    /// Pages backwards if `before_id` is set.
+    pub fn list_all(&self, params: ListParams) -> PaginatedStream<'c, MessageBatch> {
+        let batches = self.clone();
+        paginate(params, move |params| {
+            let batches = batches.clone();
+            async move { batches.list_page(&params).await }
+        })
+    }
+
+    /// Streams the results of a batch that has ended
+    ///
+    /// Results are parsed line by line as they are downloaded, so large result files are never
+    /// held in memory. Results are not in the same order as the requests; match them by
+    /// `custom_id`.
+    #[tracing::instrument(skip_all)]
+    pub async fn results(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<BatchResultStream, AnthropicError> {
+        self.client
+            .get_jsonl(&format!(
+                "/v1/messages/batches/{}/results",
+                batch_id.as_ref()
+            ))
+            .await
+    }
+
+    /// Cancels a batch; requests that are already processing will still complete
+    #[tracing::instrument(skip_all)]
+    pub async fn cancel(&self, batch_id: impl AsRef<str>) -> Result<MessageBatch, AnthropicError> {
+        self.client
+            .post(
+                &format!("/v1/messages/batches/{}/cancel", batch_id.as_ref()),
+                serde_json::json!({}),
+            )
+            .await
+    }
+
+    /// Deletes a batch, which must have finished processing
+    #[tracing::instrument(skip_all)]
+    pub async fn delete(
+        &self,
+        batch_id: impl AsRef<str>,
+    ) -> Result<DeleteMessageBatchResponse, AnthropicError> {
+        self.client
+            .delete(&format!("/v1/messages/batches/{}", batch_id.as_ref()))
+            .await
+    }
+}
+
+/// Runs an arbitrary number of requests through the message batches api
+///
+/// Requests are split into batches within the api limits and submitted. Each batch is then polled
+/// with exponential backoff until it has ended, after which its results are streamed and mapped
+/// back to the ids provided by the caller.
+///
+/// # Example
+///
+/// ```no_run
+/// # use async_anthropic::{batches::BatchJob, types::*};
+/// # use tokio_stream::StreamExt as _;
+/// # async fn run(requests: Vec<(u64, CreateMessagesRequest)>) -> Result<(), async_anthropic::errors::AnthropicError> {
+/// let client = async_anthropic::Client::default();
+///
+/// let mut results = BatchJob::new(&client).run(requests).await?;
+///
+/// while let Some(result) = results.next().await {
+///     let (id, result) = result?;
+///     println!("{id}: {result:?}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BatchJob<'c> {
+    client: &'c Client,
+    max_batch_size: usize,
+    max_batch_bytes: usize,
+    poll_backoff: ExponentialBackoff,
+}
+
+/// Batches submitted by a `BatchJob`, with the mapping from custom ids to caller ids
+#[derive(Debug)]
+pub struct SubmittedBatchJob<K> {
+    batches: Vec<(MessageBatch, HashMap<String, K>)>,
+}
+
+impl<K> SubmittedBatchJob<K> {
+    pub fn batches(&self) -> impl Iterator<Item = &MessageBatch> {
+        self.batches.iter().map(|(batch, _)| batch)
+    }
+}
+
+pub type BatchJobResultStream<'c, K> =
+    Pin<Box<dyn Stream<Item = Result<(K, BatchResultKind), AnthropicError>> + Send + 'c>>;
+
+impl<'c> BatchJob<'c> {
+    pub fn new(client: &'c Client) -> Self {
+        let poll_backoff = ExponentialBackoffBuilder::default()
+            .with_initial_interval(Duration::from_secs(10))
+            .with_multiplier(1.5)
+            .with_max_interval(Duration::from_secs(300))
+            .with_max_elapsed_time(None)
+            .build();
+
+        BatchJob {
+            client,
+            max_batch_size: MAX_BATCH_SIZE,
+            max_batch_bytes: MAX_BATCH_BYTES,
+            poll_backoff,
+        }
+    }
+
+    /// Maximum number of requests per batch
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+
+    /// Maximum size of the serialized requests per batch
+    pub fn with_max_batch_bytes(mut self, max_batch_bytes: usize) -> Self {
+        self.max_batch_bytes = max_batch_bytes.min(MAX_BATCH_BYTES);
+        self
+    }
+
+    /// Backoff used while waiting for batches to end
+    pub fn with_poll_backoff(mut self, poll_backoff: ExponentialBackoff) -> Self {
+        self.poll_backoff = poll_backoff;
+        self
+    }
+
+    /// Submits all requests and streams the results as batches end
+    pub async fn run<K, I>(
+        &self,
+        requests: I,
+    ) -> Result<BatchJobResultStream<'c, K>, AnthropicError>
+    where
+        K: Send + 'c,
+        I: IntoIterator<Item = (K, CreateMessagesRequest)>,
+    {
+        let job = self.submit(requests).await?;
+        Ok(self.results(job))
+    }
+
+    /// Splits the requests into batches and submits them
+    ///
+    /// If creating a batch fails after earlier batches were created, the error is a
+    /// `AnthropicError::PartialBatchSubmission` with the ids of the created batches, so they can
+    /// still be polled or cancelled.
+    #[tracing::instrument(skip_all)]
+    pub async fn submit<K, I>(&self, requests: I) -> Result<SubmittedBatchJob<K>, AnthropicError>
+    where
+        I: IntoIterator<Item = (K, CreateMessagesRequest)>,
+    {
+        let mut batches = vec![];
+
+        match self.create_batches(requests, &mut batches).await {
+            Ok(()) => {
+                tracing::debug!(batches = batches.len(), "Submitted batch job");
+                Ok(SubmittedBatchJob { batches })
+            }
+            Err(err) if batches.is_empty() => Err(err),
+            Err(err) => Err(AnthropicError::PartialBatchSubmission {
+                batch_ids: batches.into_iter().map(|(batch, _)| batch.id).collect(),
+                source: Box::new(err),
+            }),
+        }
+    }
+
+    async fn create_batches<K, I>(
+        &self,
+        requests: I,
+        batches: &mut Vec<(MessageBatch, HashMap<String, K>)>,
+    ) -> Result<(), AnthropicError>
+    where
+        I: IntoIterator<Item = (K, CreateMessagesRequest)>,
+    {
+        let mut pending = vec![];
+        let mut ids = HashMap::new();
+        let mut pending_bytes = 0;
+
+        for (index, (id, params)) in requests.into_iter().enumerate() {
+            let request = BatchRequest::new(format!("request-{index}"), params);
+            // Including the separator in the request list
+            let bytes = serde_json::to_vec(&request)?.len() + 1;
+
+            if !pending.is_empty()
+                && (pending.len() >= self.max_batch_size
+                    || pending_bytes + bytes > self.max_batch_bytes)
+            {
+                let batch = self
+                    .client
+                    .batches()
+                    .create(std::mem::take(&mut pending))
+                    .await?;
+                batches.push((batch, std::mem::take(&mut ids)));
+                pending_bytes = 0;
+            }
+
+            ids.insert(request.custom_id.clone(), id);
+            pending.push(request);
+            pending_bytes += bytes;
+        }
+
+        if !pending.is_empty() {
+            let batch = self.client.batches().create(pending).await?;
+            batches.push((batch, ids));
+        }
+
+        Ok(())
+    }
+
+    /// Waits for each submitted batch to end and streams its results
+    pub fn results<K>(&self, job: SubmittedBatchJob<K>) -> BatchJobResultStream<'c, K>
+    where
+        K: Send + 'c,
+    {
+        let client = self.client;
+        let poll_backoff = self.poll_backoff.clone();
+
+        let results = futures::stream::iter(job.batches).then(move |(batch, mut ids)| {
+            let mut poll_backoff = poll_backoff.clone();
+            async move {
+                let batch = wait_for_batch(client, batch, &mut poll_backoff).await?;
+                let results = client.batches().results(&batch.id).await?;
+
+                Ok::<_, AnthropicError>(results.map(move |result| {
+                    let result = result?;
+                    let id = ids.remove(&result.custom_id).ok_or_else(|| {
+                        AnthropicError::Unknown(format!(
+                            "unexpected custom_id in batch {}: {}",
+                            batch.id, result.custom_id
+                        ))
+                    })?;
+                    Ok((id, result.result))
+                }))
+            }
+        });
+
+        Box::pin(results.try_flatten())
+    }
+}
+
+async fn wait_for_batch(
+    client: &Client,
+    mut batch: MessageBatch,
+    poll_backoff: &mut ExponentialBackoff,
+) -> Result<MessageBatch, AnthropicError> {
+    poll_backoff.reset();
+
+    while batch.processing_status != BatchProcessingStatus::Ended {
+        let delay = poll_backoff
+            .next_backoff()
+            .unwrap_or(poll_backoff.max_interval);
+        tracing::debug!(batch_id = batch.id, ?delay, "Waiting for batch to end");
+        tokio::time::sleep(delay).await;
+
+        batch = client.batches().get(&batch.id).await?;
+    }
+
+    Ok(batch)
+}
@@ -1,16 +1,22 @@
-use backoff::{Error as BackoffError, ExponentialBackoff, ExponentialBackoffBuilder};
+use backoff::ExponentialBackoff;
 use derive_builder::Builder;
-use reqwest::StatusCode;
-use reqwest_eventsource::{Event, EventSource, RequestBuilderExt as _};
+use eventsource_stream::{Event, EventStreamError, Eventsource as _};
 use secrecy::ExposeSecret;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{pin::Pin, time::Duration};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 use tokio_stream::{Stream, StreamExt as _};
 
 use crate::{
-    errors::{map_deserialization_error, AnthropicError, StreamError},
+    batches::Batches,
+    errors::{map_deserialization_error, AnthropicError, ApiError, StreamError},
+    files::Files,
     messages::Messages,
     models::Models,
+    retry::{DefaultRetryPolicy, RetryContext, RetryPolicy},
 };
 
 const BASE_URL: &str = "https://api.anthropic.com";
@@ -52,31 +58,40 @@ pub struct Client {
     version: String,
     #[builder(default)]
     beta: Option<String>,
-    #[builder(default)]
-    backoff: ExponentialBackoff,
+    #[builder(default = "default_retry_policy()", setter(custom))]
+    retry_policy: Arc<dyn RetryPolicy>,
 }
 
 impl Default for Client {
     fn default() -> Self {
-        // Load backoff settings from configuration
-        let backoff = ExponentialBackoffBuilder::default()
-            .with_initial_interval(Duration::from_secs(15))
-            .with_multiplier(2.0)
-            .with_randomization_factor(0.05)
-            .with_max_elapsed_time(Some(Duration::from_secs(120)))
-            .build();
-
         Self {
             http_client: reqwest::Client::new(),
             api_key: default_api_key(), // Default env?
             version: "2023-06-01".to_string(),
             beta: None,
             base_url: BASE_URL.to_string(),
-            backoff,
+            retry_policy: default_retry_policy(),
         }
     }
 }
 
+fn default_retry_policy() -> Arc<dyn RetryPolicy> {
+    Arc::new(DefaultRetryPolicy::default())
+}
+
+impl ClientBuilder {
+    /// Set the policy deciding which failed requests are retried
+    pub fn retry_policy(&mut self, retry_policy: impl RetryPolicy + 'static) -> &mut Self {
+        self.retry_policy = Some(Arc::new(retry_policy));
+        self
+    }
+
+    /// Retry all transient errors with the same backoff
+    pub fn backoff(&mut self, backoff: ExponentialBackoff) -> &mut Self {
+        self.retry_policy(backoff)
+    }
+}
+
 fn default_api_key() -> secrecy::SecretString {
     if cfg!(test) {
         return "test".into();
@@ -103,21 +118,50 @@ impl Client {
         ClientBuilder::default()
     }
 
-    /// Set a custom backoff strategy
-    pub fn with_backoff(mut self, backoff: ExponentialBackoff) -> Self {
-        self.backoff = backoff;
+    /// Set a custom backoff strategy, used for all transient errors
+    pub fn with_backoff(self, backoff: ExponentialBackoff) -> Self {
+        self.with_retry_policy(backoff)
+    }
+
+    /// Set the policy deciding which failed requests are retried
+    pub fn with_retry_policy(mut self, retry_policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Arc::new(retry_policy);
         self
     }
 
     /// Call the messages api
-    pub fn messages(&self) -> Messages {
+    pub fn messages(&self) -> Messages<'_> {
         Messages::new(self)
     }
 
-    pub fn models(&self) -> Models {
+    pub fn models(&self) -> Models<'_> {
         Models::new(self)
     }
 
+    /// Call the message batches api
+    pub fn batches(&self) -> Batches<'_> {
+        Batches::new(self)
+    }
+
+    /// Call the files api
+    pub fn files(&self) -> Files<'_> {
+        Files::new(self)
+    }
+
+    /// Returns a copy of the client with an additional beta feature enabled
+    pub fn with_beta_feature(&self, feature: &str) -> Self {
+        let beta = match &self.beta {
+            Some(beta) if beta.split(',').any(|f| f.trim() == feature) => beta.clone(),
+            Some(beta) => format!("{beta},{feature}"),
+            None => feature.to_string(),
+        };
+
+        Self {
+            beta: Some(beta),
+            ..self.clone()
+        }
+    }
+
     fn headers(&self) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert("x-api-key", self.api_key.expose_secret().parse().unwrap());
@@ -140,49 +184,109 @@ impl Client {
     where
         O: DeserializeOwned,
     {
-        backoff::future::retry(self.backoff.clone(), || async {
-            let response = self
-                .http_client
-                .get(self.format_url(path))
-                .headers(self.headers())
-                .send()
-                .await
-                .map_err(AnthropicError::NetworkError)
-                .map_err(backoff::Error::Permanent)?;
-
-            let status = response.status();
-
-            match status {
-                StatusCode::OK => {
-                    let response = response
-                        .json::<O>()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-                    Ok(response)
-                }
-                StatusCode::BAD_REQUEST => {
-                    let text = response
-                        .text()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-                    Err(BackoffError::Permanent(AnthropicError::BadRequest(text)))
-                }
-                StatusCode::UNAUTHORIZED => {
-                    Err(BackoffError::Permanent(AnthropicError::Unauthorized))
-                }
-                _ => {
-                    let text = response
-                        .text()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-                    Err(BackoffError::Permanent(AnthropicError::Unknown(text)))
-                }
-            }
+        self.send(|| self.http_client.get(self.format_url(path)))
+            .await?
+            .json::<O>()
+            .await
+            .map_err(AnthropicError::NetworkError)
+    }
+
+    pub async fn delete<O>(&self, path: &str) -> Result<O, AnthropicError>
+    where
+        O: DeserializeOwned,
+    {
+        self.send(|| self.http_client.delete(self.format_url(path)))
+            .await?
+            .json::<O>()
+            .await
+            .map_err(AnthropicError::NetworkError)
+    }
+
+    /// Make a get request and return the raw response body
+    pub async fn get_bytes(&self, path: &str) -> Result<Vec<u8>, AnthropicError> {
+        let bytes = self
+            .send(|| self.http_client.get(self.format_url(path)))
+            .await?
+            .bytes()
+            .await
+            .map_err(AnthropicError::NetworkError)?;
+
+        Ok(bytes.to_vec())
+    }
+
+    /// Make a multipart post request, i.e. to upload files
+    ///
+    /// The form is rebuilt for every attempt.
+    pub async fn post_multipart<F, O>(&self, path: &str, form: F) -> Result<O, AnthropicError>
+    where
+        F: Fn() -> reqwest::multipart::Form,
+        O: DeserializeOwned,
+    {
+        self.send(|| {
+            self.http_client
+                .post(self.format_url(path))
+                .multipart(form())
         })
+        .await?
+        .json::<O>()
         .await
+        .map_err(AnthropicError::NetworkError)
+    }
+
+    /// Sends a request with retries, returning the response if it was successful
+    ///
+    /// The retry policy decides after every failed attempt whether to retry. Delays requested by
+    /// the response headers are passed along to the policy.
+    async fn send<F>(&self, request: F) -> Result<reqwest::Response, AnthropicError>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        self.send_with(request, |response| async move { Ok(response) })
+            .await
+    }
+
+    /// Like `send`, but also retries if `open` fails on a successful response
+    ///
+    /// Used to retry streams that fail with an `error` event before sending anything else.
+    async fn send_with<F, G, Fut, T>(&self, request: F, open: G) -> Result<T, AnthropicError>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+        G: Fn(reqwest::Response) -> Fut,
+        Fut: std::future::Future<Output = Result<T, AnthropicError>>,
+    {
+        let start = Instant::now();
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let (error, retry_after) = match request().headers(self.headers()).send().await {
+                Ok(response) if response.status().is_success() => match open(response).await {
+                    Ok(opened) => return Ok(opened),
+                    Err(error) => (error, None),
+                },
+                Ok(response) => {
+                    let retry_after = retry_after(response.headers());
+                    (api_error(response).await, retry_after)
+                }
+                Err(err) => (AnthropicError::NetworkError(err), None),
+            };
+
+            let context = RetryContext {
+                attempt,
+                error: &error,
+                retry_after,
+                elapsed: start.elapsed(),
+            };
+
+            let Some(delay) = self.retry_policy.retry_delay(&context) else {
+                return Err(error);
+            };
+
+            tracing::warn!(?delay, attempt, "Retrying request: {error}");
+            self.retry_policy.on_retry(&context, delay);
+            tokio::time::sleep(delay).await;
+        }
     }
 
     /// Make post request to the API
@@ -193,73 +297,26 @@ impl Client {
         I: Serialize,
         O: DeserializeOwned,
     {
-        backoff::future::retry(self.backoff.clone(), || async {
-            let mut request = self
-                .http_client
-                .post(self.format_url(path))
-                .headers(self.headers())
-                .json(&request);
-
-            if let Some(beta_value) = &self.beta {
-                request = request.header("anthropic-beta", beta_value);
-            }
+        self.send(|| self.http_client.post(self.format_url(path)).json(&request))
+            .await?
+            .json::<O>()
+            .await
+            .map_err(AnthropicError::NetworkError)
+    }
 
-            let response = request
-                .send()
-                .await
-                .map_err(AnthropicError::NetworkError)
-                .map_err(backoff::Error::Permanent)?;
-            let status = response.status();
-
-            // 529 is the status code for overloaded requests
-            let overloaded_status = StatusCode::from_u16(529).expect("529 is a valid status code");
-
-            match status {
-                StatusCode::OK => {
-                    let response = response
-                        .json::<O>()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-                    Ok(response)
-                }
-                StatusCode::BAD_REQUEST => {
-                    let text = response
-                        .text()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-                    Err(BackoffError::Permanent(AnthropicError::BadRequest(text)))
-                }
-                StatusCode::UNAUTHORIZED => {
-                    Err(BackoffError::Permanent(AnthropicError::Unauthorized))
-                }
+    /// Streams a jsonl response line by line, without buffering the whole body
+    pub(crate) async fn get_jsonl<O>(
+        &self,
+        path: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>, AnthropicError>
+    where
+        O: DeserializeOwned + Send + 'static,
+    {
+        let response = self
+            .send(|| self.http_client.get(self.format_url(path)))
+            .await?;
 
-                _ if status == StatusCode::TOO_MANY_REQUESTS || status == overloaded_status => {
-                    let text = response
-                        .text()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-
-                    // Rate limited retry...
-                    tracing::warn!("Rate limited: {}", text);
-                    Err(backoff::Error::Transient {
-                        err: AnthropicError::ApiError(text),
-                        retry_after: None,
-                    })
-                }
-                _ => {
-                    let text = response
-                        .text()
-                        .await
-                        .map_err(AnthropicError::NetworkError)
-                        .map_err(backoff::Error::Permanent)?;
-                    Err(BackoffError::Permanent(AnthropicError::Unknown(text)))
-                }
-            }
-        })
-        .await
+        Ok(jsonl_stream(response.bytes_stream()))
     }
 
     pub(crate) async fn post_stream<I, O, const N: usize>(
@@ -272,86 +329,280 @@ impl Client {
         I: Serialize,
         O: DeserializeOwned + Send + 'static,
     {
-        let event_source = self
-            .http_client
-            .post(self.format_url(path))
-            .headers(self.headers())
-            .json(&request)
-            .eventsource()
-            .unwrap();
+        // Retries and status handling happen before the stream is opened, so callers only see
+        // events of a successful response. An `error` event or a failed connection before any
+        // other event is retried like an error status.
+        let opened = self
+            .send_with(
+                || {
+                    self.http_client
+                        .post(self.format_url(path))
+                        .header(reqwest::header::ACCEPT, "text/event-stream")
+                        .json(&request)
+                },
+                |response| async move {
+                    let request_id = request_id(response.headers());
+                    let mut events = response.bytes_stream().eventsource();
+
+                    let first = loop {
+                        match events.next().await {
+                            Some(Ok(event)) if event.event == "ping" => continue,
+                            Some(Ok(event)) if event.event == "error" => {
+                                return Err(stream_error(request_id, &event.data))
+                            }
+                            Some(Err(EventStreamError::Transport(err))) => {
+                                return Err(AnthropicError::NetworkError(err))
+                            }
+                            first => break first,
+                        }
+                    };
 
-        stream(event_source, event_types).await
+                    Ok((request_id, tokio_stream::iter(first).chain(events)))
+                },
+            )
+            .await;
+
+        match opened {
+            Ok((request_id, events)) => stream(events, event_types, request_id).await,
+            Err(err) => Box::pin(tokio_stream::once(Err(err))),
+        }
     }
 }
 
-async fn stream<O, const N: usize>(
-    mut event_source: EventSource,
+/// Longest delay taken from the response headers
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Reads how long to wait before retrying from the response headers
+///
+/// Prefers `retry-after`, in seconds or as an http date, and falls back to the latest reset of the
+/// exhausted `anthropic-ratelimit-*` limits. Delays are capped at an hour.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let until = |time: SystemTime| time.duration_since(SystemTime::now()).unwrap_or_default();
+
+    let delay = match header("retry-after").map(str::trim) {
+        Some(value) => match value.parse::<f64>() {
+            Ok(seconds) if seconds >= 0.0 => Duration::try_from_secs_f64(seconds)
+                .ok()
+                .or(Some(MAX_RETRY_AFTER)),
+            Ok(_) => None,
+            Err(_) => httpdate::parse_http_date(value).ok().map(until),
+        },
+        None => None,
+    };
+
+    delay
+        .or_else(|| {
+            ["requests", "tokens", "input-tokens", "output-tokens"]
+                .iter()
+                .filter(|limit| {
+                    header(&format!("anthropic-ratelimit-{limit}-remaining"))
+                        .and_then(|remaining| remaining.trim().parse::<u64>().ok())
+                        == Some(0)
+                })
+                .filter_map(|limit| header(&format!("anthropic-ratelimit-{limit}-reset")))
+                .filter_map(|reset| humantime::parse_rfc3339_weak(reset.trim()).ok())
+                .map(until)
+                .max()
+        })
+        .map(|delay| delay.min(MAX_RETRY_AFTER))
+}
+
+/// Reads an error response into an `AnthropicError`
+async fn api_error(response: reqwest::Response) -> AnthropicError {
+    let status = response.status().as_u16();
+    let request_id = request_id(response.headers());
+
+    match response.text().await {
+        Ok(body) => AnthropicError::ApiError(ApiError::from_response(status, request_id, &body)),
+        Err(err) => AnthropicError::NetworkError(err),
+    }
+}
+
+fn request_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get("request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Reads an `error` event sent after the stream was opened
+///
+/// The event has the same body as an error response, and keeps the status of the stream.
+fn stream_error(request_id: Option<String>, data: &str) -> AnthropicError {
+    AnthropicError::ApiError(ApiError::from_response(200, request_id, data))
+}
+
+async fn stream<S, O, const N: usize>(
+    mut events: S,
     event_types: [&'static str; N],
+    request_id: Option<String>,
 ) -> Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>
 where
+    S: Stream<Item = Result<Event, EventStreamError<reqwest::Error>>> + Send + Unpin + 'static,
     O: DeserializeOwned + Send + 'static,
 {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
     tokio::spawn(async move {
-        while let Some(ev) = event_source.next().await {
+        while let Some(ev) = events.next().await {
             tracing::trace!("Streaming event: {ev:?}");
-            match ev {
-                Ok(event) => match event {
-                    Event::Open => continue,
-                    Event::Message(message) => {
-                        let event = message.event.as_str();
-                        if event == "ping" {
-                            continue;
-                        }
+            let response = match ev {
+                Ok(message) => {
+                    let event = message.event.as_str();
+                    if event == "ping" {
+                        continue;
+                    }
 
-                        let response = if event == "error" {
-                            match serde_json::from_str::<StreamError>(&message.data) {
-                                Ok(e) => Err(AnthropicError::StreamError(e)),
-                                Err(e) => {
-                                    Err(map_deserialization_error(e, message.data.as_bytes()))
-                                }
-                            }
-                        } else if event_types.contains(&event) {
-                            match serde_json::from_str::<O>(&message.data) {
-                                Ok(output) => Ok(output),
-                                Err(e) => {
-                                    Err(map_deserialization_error(e, message.data.as_bytes()))
-                                }
+                    if event == "error" {
+                        Err(stream_error(request_id.clone(), &message.data))
+                    } else if event_types.contains(&event) {
+                        match serde_json::from_str::<O>(&message.data) {
+                            Ok(output) => Ok(output),
+                            Err(e) => Err(map_deserialization_error(e, message.data.as_bytes())),
+                        }
+                    } else {
+                        // Forward new event types if the output can represent them, i.e. as
+                        // `MessagesStreamEvent::Unknown`, and skip them otherwise
+                        match serde_json::from_str::<O>(&message.data) {
+                            Ok(output) => Ok(output),
+                            Err(_) => {
+                                tracing::debug!("Skipping unknown event type: {event}");
+                                continue;
                             }
-                        } else {
-                            Err(AnthropicError::StreamError(StreamError {
-                                error_type: "unknown_event_type".to_string(),
-                                message: format!("Unknown event type: {event}"),
-                            }))
-                        };
-                        let cancel = response.is_err();
-                        if tx.send(response).is_err() || cancel {
-                            // rx dropped or other error
-                            break;
                         }
                     }
-                },
-                Err(e) => {
-                    if let reqwest_eventsource::Error::StreamEnded = e {
-                        break;
-                    }
-                    if tx
-                        .send(Err(AnthropicError::StreamError(StreamError {
-                            error_type: "sse_error".to_string(),
-                            message: e.to_string(),
-                        })))
-                        .is_err()
-                    {
-                        // rx dropped
-                        break;
-                    }
                 }
+                Err(EventStreamError::Transport(e)) => Err(AnthropicError::NetworkError(e)),
+                Err(e) => Err(AnthropicError::StreamError(StreamError {
+                    error_type: "sse_error".to_string(),
+                    message: e.to_string(),
+                })),
+            };
+
+            let cancel = response.is_err();
+            if tx.send(response).is_err() || cancel {
+                // rx dropped or other error
+                break;
             }
         }
-
-        event_source.close();
     });
 
     Box::pin(tokio_stream::wrappers::UnboundedReceiverStream::new(rx))
 }
+
+/// Splits a stream of bytes into lines and deserializes each non-empty line
+fn jsonl_stream<O, S, B>(bytes: S) -> Pin<Box<dyn Stream<Item = Result<O, AnthropicError>> + Send>>
+where
+    O: DeserializeOwned + Send + 'static,
+    S: Stream<Item = Result<B, reqwest::Error>> + Send + 'static,
+    B: AsRef<[u8]>,
+{
+    fn parse<O: DeserializeOwned>(line: &[u8]) -> Result<O, AnthropicError> {
+        serde_json::from_slice(line).map_err(|e| map_deserialization_error(e, line))
+    }
+
+    let state = (Box::pin(bytes), Vec::<u8>::new(), false);
+
+    Box::pin(futures::stream::unfold(
+        state,
+        |(mut bytes, mut buffer, mut done)| async move {
+            loop {
+                if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                    let line = buffer.drain(..=pos).collect::<Vec<_>>();
+                    if line.trim_ascii().is_empty() {
+                        continue;
+                    }
+                    return Some((parse(line.trim_ascii()), (bytes, buffer, done)));
+                }
+
+                if done {
+                    if buffer.trim_ascii().is_empty() {
+                        return None;
+                    }
+                    let line = std::mem::take(&mut buffer);
+                    return Some((parse(line.trim_ascii()), (bytes, buffer, done)));
+                }
+
+                match bytes.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(chunk.as_ref()),
+                    Some(Err(e)) => {
+                        // The body is broken; drop whatever is left
+                        buffer.clear();
+                        return Some((Err(AnthropicError::NetworkError(e)), (bytes, buffer, true)));
+                    }
+                    None => done = true,
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jsonl_stream_splits_chunks_on_newlines() {
+        let chunks = ["{\"a\":", " 1}\n\n{\"a\"", ": 2}\n{\"a\": 3}"]
+            .map(|chunk| Ok::<_, reqwest::Error>(chunk.as_bytes()));
+
+        let lines = jsonl_stream::<Value, _, _>(tokio_stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            lines,
+            vec![json!({"a": 1}), json!({"a": 2}), json!({"a": 3})]
+        );
+    }
+
+    #[test]
+    fn test_retry_after_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        let reset = humantime::format_rfc3339(SystemTime::now() + Duration::from_secs(30));
+        headers.insert(
+            "anthropic-ratelimit-requests-remaining",
+            "10".parse().unwrap(),
+        );
+        headers.insert(
+            "anthropic-ratelimit-requests-reset",
+            "2000-01-01T00:00:00Z".parse().unwrap(),
+        );
+        headers.insert("anthropic-ratelimit-tokens-remaining", "0".parse().unwrap());
+        headers.insert(
+            "anthropic-ratelimit-tokens-reset",
+            reset.to_string().parse().unwrap(),
+        );
+
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+
+        headers.insert("retry-after", "1e300".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(MAX_RETRY_AFTER));
+
+        headers.insert("retry-after", "inf".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(MAX_RETRY_AFTER));
+
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+        headers.insert("retry-after", date.parse().unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+
+        headers.insert(
+            "retry-after",
+            "Sat, 01 Jan 2000 00:00:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+}
@@ -6,14 +6,16 @@ pub enum AnthropicError {
     #[error("network error: {0}")]
     NetworkError(#[from] reqwest::Error),
 
+    /// The request was rejected before it was sent
     #[error("malformed request: {0}")]
     BadRequest(String),
 
+    /// The api responded with an error
     #[error("api error: {0}")]
-    ApiError(String),
+    ApiError(ApiError),
 
-    #[error("unauthorized; check your API key")]
-    Unauthorized,
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
 
     #[error("failed to deserialize response: {0}")]
     DeserializationError(#[from] serde_json::Error),
@@ -26,6 +28,40 @@ pub enum AnthropicError {
 
     #[error("stream failed: {0}")]
     StreamError(StreamError),
+
+    /// A `BatchJob` failed to create a batch after creating others, which are processed and
+    /// billed regardless
+    #[error("failed to submit batch job after creating batches {batch_ids:?}: {source}")]
+    PartialBatchSubmission {
+        batch_ids: Vec<String>,
+        source: Box<AnthropicError>,
+    },
+}
+
+impl AnthropicError {
+    /// The kind of error returned by the api, if any
+    pub fn kind(&self) -> Option<ApiErrorKind> {
+        match self {
+            AnthropicError::ApiError(error) => Some(error.kind),
+            _ => None,
+        }
+    }
+
+    /// The http status of the error response, if any
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            AnthropicError::ApiError(error) => Some(error.status),
+            _ => None,
+        }
+    }
+
+    /// The `request-id` of the error response, useful when contacting support
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            AnthropicError::ApiError(error) => error.request_id.as_deref(),
+            _ => None,
+        }
+    }
 }
 
 impl From<backoff::Error<AnthropicError>> for AnthropicError {
@@ -37,6 +73,120 @@ impl From<backoff::Error<AnthropicError>> for AnthropicError {
     }
 }
 
+/// An error response from the api
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub kind: ApiErrorKind,
+    pub message: String,
+    pub status: u16,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    /// Parses an error response body
+    ///
+    /// Bodies that are not in the api's error format, i.e. from a proxy, are kept as the message
+    /// and the kind is derived from the status instead.
+    pub fn from_response(status: u16, request_id: Option<String>, body: &str) -> Self {
+        let (kind, message) = match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(response) => (response.error.kind, response.error.message),
+            Err(_) => (ApiErrorKind::from_status(status), body.to_string()),
+        };
+
+        ApiError {
+            kind,
+            message,
+            status,
+            request_id,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.kind, self.status, self.message)?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " [request-id: {request_id}]")?;
+        }
+        Ok(())
+    }
+}
+
+/// The error types returned by the api
+///
+/// See <https://docs.anthropic.com/en/api/errors>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiErrorKind {
+    #[serde(rename = "invalid_request_error")]
+    InvalidRequest,
+    #[serde(rename = "authentication_error")]
+    Authentication,
+    #[serde(rename = "permission_error")]
+    Permission,
+    #[serde(rename = "not_found_error")]
+    NotFound,
+    #[serde(rename = "request_too_large")]
+    RequestTooLarge,
+    #[serde(rename = "rate_limit_error")]
+    RateLimit,
+    #[serde(rename = "api_error")]
+    Api,
+    #[serde(rename = "overloaded_error")]
+    Overloaded,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ApiErrorKind {
+    /// Derives the kind from the http status, for responses without an error body
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            400 => ApiErrorKind::InvalidRequest,
+            401 => ApiErrorKind::Authentication,
+            403 => ApiErrorKind::Permission,
+            404 => ApiErrorKind::NotFound,
+            413 => ApiErrorKind::RequestTooLarge,
+            429 => ApiErrorKind::RateLimit,
+            529 => ApiErrorKind::Overloaded,
+            500..=599 => ApiErrorKind::Api,
+            _ => ApiErrorKind::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiErrorKind::InvalidRequest => "invalid_request_error",
+            ApiErrorKind::Authentication => "authentication_error",
+            ApiErrorKind::Permission => "permission_error",
+            ApiErrorKind::NotFound => "not_found_error",
+            ApiErrorKind::RequestTooLarge => "request_too_large",
+            ApiErrorKind::RateLimit => "rate_limit_error",
+            ApiErrorKind::Api => "api_error",
+            ApiErrorKind::Overloaded => "overloaded_error",
+            ApiErrorKind::Unknown => "unknown_error",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The error body returned by the api, i.e. `{"type": "error", "error": {...}}`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetails,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorDetails {
+    #[serde(rename = "type")]
+    pub kind: ApiErrorKind,
+    pub message: String,
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq, Serialize)]
 pub struct StreamError {
     #[serde(rename = "type")]
@@ -56,3 +206,31 @@ impl std::fmt::Display for StreamError {
 pub(crate) fn map_deserialization_error(e: serde_json::Error, _bytes: &[u8]) -> AnthropicError {
     AnthropicError::DeserializationError(e)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_from_response() {
+        let body =
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#;
+        let error = ApiError::from_response(529, Some("req_1".to_string()), body);
+
+        assert_eq!(error.kind, ApiErrorKind::Overloaded);
+        assert_eq!(error.message, "Overloaded");
+        assert_eq!(
+            error.to_string(),
+            "overloaded_error (529): Overloaded [request-id: req_1]"
+        );
+
+        let error = ApiError::from_response(404, None, "Not Found");
+        assert_eq!(error.kind, ApiErrorKind::NotFound);
+        assert_eq!(error.message, "Not Found");
+
+        let body =
+            r#"{"type": "error", "error": {"type": "billing_error", "message": "No credits"}}"#;
+        let error = ApiError::from_response(400, None, body);
+        assert_eq!(error.kind, ApiErrorKind::Unknown);
+    }
+}
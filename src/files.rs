@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use reqwest::multipart::{Form, Part};
+
+use crate::{
+    errors::AnthropicError,
+    pagination::{paginate, PaginatedStream},
+    types::{DeleteFileResponse, FileMetadata, ListFilesResponse, ListParams},
+    Client,
+};
+
+/// Beta header value required by the files api
+///
+/// Requests to the messages api that reference files by id need it as well, see
+/// [`Client::with_beta_feature`].
+pub const FILES_API_BETA: &str = "files-api-2025-04-14";
+
+#[derive(Debug, Clone)]
+pub struct Files<'c> {
+    client: &'c Client,
+}
+
+impl<'c> Files<'c> {
+    pub fn new(client: &Client) -> Files<'_> {
+        Files { client }
+    }
+
+    /// Uploads a file so it can be referenced by id in messages
+    #[tracing::instrument(skip_all)]
+    pub async fn upload(
+        &self,
+        filename: impl Into<String>,
+        mime_type: impl AsRef<str>,
+        bytes: impl Into<Bytes>,
+    ) -> Result<FileMetadata, AnthropicError> {
+        let filename = filename.into();
+        let bytes: Bytes = bytes.into();
+        let mime_type = mime_type.as_ref();
+
+        // Validate the mime type once, so building the form for every attempt cannot fail
+        Part::bytes(vec![])
+            .mime_str(mime_type)
+            .map_err(|_| AnthropicError::BadRequest(format!("invalid mime type: {mime_type}")))?;
+
+        self.client()
+            .post_multipart("/v1/files", || {
+                // Cloning `Bytes` shares the data instead of copying it for every attempt
+                let part = Part::stream(bytes.clone())
+                    .file_name(filename.clone())
+                    .mime_str(mime_type)
+                    .expect("mime type is validated");
+                Form::new().part("file", part)
+            })
+            .await
+    }
+
+    /// Reads a file from disk and uploads it, inferring the mime type from the extension
+    pub async fn upload_path(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<FileMetadata, AnthropicError> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                AnthropicError::BadRequest(format!("invalid file name: {}", path.display()))
+            })?;
+        let mime_type = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or("application/octet-stream", mime_type_from_extension);
+        let bytes = tokio::fs::read(path).await?;
+
+        self.upload(filename, mime_type, bytes).await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn list(&self) -> Result<ListFilesResponse, AnthropicError> {
+        self.list_page(&ListParams::default()).await
+    }
+
+    /// Lists a single page of files
+    #[tracing::instrument(skip_all)]
+    pub async fn list_page(
+        &self,
+        params: &ListParams,
+    ) -> Result<ListFilesResponse, AnthropicError> {
+        self.client().get(&params.to_path("/v1/files")).await
+    }
+
+    /// Streams all files from the position in `params`, fetching pages of `params.limit` as needed
+    ///
+    /// Pages backwards if `before_id` is set.
+    pub fn list_all(&self, params: ListParams) -> PaginatedStream<'c, FileMetadata> {
+        let client = self.client();
+        paginate(params, move |params| {
+            let client = client.clone();
+            async move {
+                client
+                    .get::<ListFilesResponse>(&params.to_path("/v1/files"))
+                    .await
+            }
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn get(&self, file_id: impl AsRef<str>) -> Result<FileMetadata, AnthropicError> {
+        self.client()
+            .get(&format!("/v1/files/{}", file_id.as_ref()))
+            .await
+    }
+
+    /// Downloads the content of a file
+    #[tracing::instrument(skip_all)]
+    pub async fn download(&self, file_id: impl AsRef<str>) -> Result<Vec<u8>, AnthropicError> {
+        self.client()
+            .get_bytes(&format!("/v1/files/{}/content", file_id.as_ref()))
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn delete(
+        &self,
+        file_id: impl AsRef<str>,
+    ) -> Result<DeleteFileResponse, AnthropicError> {
+        self.client()
+            .delete(&format!("/v1/files/{}", file_id.as_ref()))
+            .await
+    }
+
+    fn client(&self) -> Client {
+        self.client.with_beta_feature(FILES_API_BETA)
+    }
+}
+
+fn mime_type_from_extension(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
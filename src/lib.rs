@@ -1,6 +1,13 @@
+pub mod batches;
 mod client;
 pub mod errors;
+pub mod files;
 pub mod messages;
 pub mod models;
+pub mod pagination;
+pub mod pricing;
+pub mod retry;
+pub mod stream;
+pub mod tool_runner;
 pub mod types;
 pub use client::Client;
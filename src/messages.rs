@@ -1,6 +1,10 @@
 use crate::{
     errors::AnthropicError,
-    types::{CreateMessagesRequest, CreateMessagesResponse, CreateMessagesResponseStream},
+    types::{
+        CountTokensRequest, CountTokensResponse, CreateMessagesRequest, CreateMessagesResponse,
+        CreateMessagesResponseStream, Message, MessageContent, MessageContentList, MessageRole,
+        StopReason, ThinkingConfig, Usage,
+    },
     Client,
 };
 
@@ -9,11 +13,44 @@ pub const DEFAULT_MAX_TOKENS: i32 = 2048;
 #[derive(Debug, Clone)]
 pub struct Messages<'c> {
     client: &'c Client,
+    continuation_max_tokens: Option<u32>,
 }
 
 impl Messages<'_> {
-    pub fn new(client: &Client) -> Messages {
-        Messages { client }
+    pub fn new(client: &Client) -> Messages<'_> {
+        Messages {
+            client,
+            continuation_max_tokens: None,
+        }
+    }
+
+    /// Continues responses that were cut off at `max_tokens` in `create`
+    ///
+    /// The partial output is sent back as an assistant prefill and the continuations are
+    /// concatenated into a single response, until the model stops for another reason or
+    /// `max_total_tokens` output tokens have been generated in total. The usage of all requests is
+    /// summed.
+    ///
+    /// Responses are returned as is when extended thinking is enabled, as it does not support
+    /// prefills, or when the output was cut off in the middle of a tool use.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use async_anthropic::types::*;
+    /// # async fn run(request: CreateMessagesRequest) -> Result<(), async_anthropic::errors::AnthropicError> {
+    /// let client = async_anthropic::Client::default();
+    /// let report = client
+    ///     .messages()
+    ///     .with_continuation(16_000)
+    ///     .create(request)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_continuation(mut self, max_total_tokens: u32) -> Self {
+        self.continuation_max_tokens = Some(max_total_tokens);
+        self
     }
 
     #[tracing::instrument(skip_all)]
@@ -24,7 +61,94 @@ impl Messages<'_> {
         let mut request = request.into();
         request.stream = false;
 
-        self.client.post("/v1/messages", request).await
+        let response = self.client.post("/v1/messages", &request).await?;
+
+        match self.continuation_max_tokens {
+            Some(max_total_tokens) => {
+                self.continue_response(request, response, max_total_tokens)
+                    .await
+            }
+            None => Ok(response),
+        }
+    }
+
+    async fn continue_response(
+        &self,
+        mut request: CreateMessagesRequest,
+        mut response: CreateMessagesResponse,
+        max_total_tokens: u32,
+    ) -> Result<CreateMessagesResponse, AnthropicError> {
+        if matches!(request.thinking, Some(ThinkingConfig::Enabled { .. })) {
+            return Ok(response);
+        }
+
+        // An existing prefill is part of the same assistant turn
+        let prefill = match request.messages.last() {
+            Some(message) if message.role == MessageRole::Assistant => {
+                request.messages.pop().map(|message| message.content.0)
+            }
+            _ => None,
+        }
+        .unwrap_or_default();
+        let messages = std::mem::take(&mut request.messages);
+        let max_tokens = request.max_tokens;
+
+        let mut content = response.content.take().unwrap_or_default();
+        let mut total_output_tokens = output_tokens(response.usage.as_ref(), max_tokens);
+
+        while response.stop_reason == Some(StopReason::MaxTokens)
+            && total_output_tokens < max_total_tokens
+        {
+            // The api rejects prefills ending in whitespace or with empty text blocks, and a
+            // partial tool use cannot be continued
+            while let Some(MessageContent::Text(text)) = content.last_mut() {
+                text.text.truncate(text.text.trim_end().len());
+                if !text.text.is_empty() {
+                    break;
+                }
+                content.pop();
+            }
+            if !matches!(content.last(), Some(MessageContent::Text(_))) {
+                break;
+            }
+
+            let remaining =
+                i32::try_from(max_total_tokens - total_output_tokens).unwrap_or(i32::MAX);
+            request.max_tokens = max_tokens.min(remaining);
+            request.messages = messages.clone();
+            request.messages.push(Message {
+                role: MessageRole::Assistant,
+                content: MessageContentList(prefill.iter().chain(&content).cloned().collect()),
+            });
+
+            tracing::debug!(
+                total_output_tokens,
+                "Continuing response cut off at max_tokens"
+            );
+            let continuation: CreateMessagesResponse =
+                self.client.post("/v1/messages", &request).await?;
+
+            total_output_tokens += output_tokens(continuation.usage.as_ref(), request.max_tokens);
+            // Only the first block continues the cut off text, later blocks are kept as they are
+            let mut blocks = continuation.content.unwrap_or_default().into_iter();
+            match (content.last_mut(), blocks.next()) {
+                (Some(MessageContent::Text(text)), Some(MessageContent::Text(next))) => {
+                    text.text.push_str(&next.text);
+                }
+                (_, block) => content.extend(block),
+            }
+            content.extend(blocks);
+
+            response.stop_reason = continuation.stop_reason;
+            response.stop_sequence = continuation.stop_sequence;
+            response.usage = match (response.usage, continuation.usage) {
+                (Some(usage), Some(next)) => Some(usage + next),
+                (usage, next) => usage.or(next),
+            };
+        }
+
+        response.content = Some(content);
+        Ok(response)
     }
 
     #[tracing::instrument(skip_all)]
@@ -50,4 +174,24 @@ impl Messages<'_> {
             )
             .await
     }
+
+    /// Counts the input tokens of a request without creating a message
+    ///
+    /// Accepts a `CreateMessagesRequest` as well.
+    #[tracing::instrument(skip_all)]
+    pub async fn count_tokens(
+        &self,
+        request: impl Into<CountTokensRequest>,
+    ) -> Result<CountTokensResponse, AnthropicError> {
+        self.client
+            .post("/v1/messages/count_tokens", request.into())
+            .await
+    }
+}
+
+// Falls back to the requested maximum if the usage is missing, so continuing always terminates
+fn output_tokens(usage: Option<&Usage>, max_tokens: i32) -> u32 {
+    usage
+        .and_then(|usage| usage.output_tokens)
+        .unwrap_or_else(|| u32::try_from(max_tokens).unwrap_or_default().max(1))
 }
@@ -1,6 +1,7 @@
 use crate::{
     errors::AnthropicError,
-    types::{GetModelResponse, ListModelsResponse},
+    pagination::{paginate, PaginatedStream},
+    types::{GetModelResponse, ListModelsResponse, ListParams, Model},
     Client,
 };
 
@@ -11,14 +12,34 @@ pub struct Models<'c> {
     client: &'c Client,
 }
 
-impl Models<'_> {
-    pub fn new(client: &Client) -> Models {
+impl<'c> Models<'c> {
+    pub fn new(client: &Client) -> Models<'_> {
         Models { client }
     }
 
     #[tracing::instrument(skip_all)]
     pub async fn list(&self) -> Result<ListModelsResponse, AnthropicError> {
-        self.client.get("/v1/models").await
+        self.list_page(&ListParams::default()).await
+    }
+
+    /// Lists a single page of models
+    #[tracing::instrument(skip_all)]
+    pub async fn list_page(
+        &self,
+        params: &ListParams,
+    ) -> Result<ListModelsResponse, AnthropicError> {
+        self.client.get(&params.to_path("/v1/models")).await
+    }
+
+    /// Streams all models from the position in `params`, fetching pages of `params.limit` as needed
+    ///
+    /// Pages backwards if `before_id` is set.
+    pub fn list_all(&self, params: ListParams) -> PaginatedStream<'c, Model> {
+        let models = self.clone();
+        paginate(params, move |params| {
+            let models = models.clone();
+            async move { models.list_page(&params).await }
+        })
     }
 
     #[tracing::instrument(skip_all)]
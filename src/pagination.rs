@@ -0,0 +1,88 @@
+//! Cursor based pagination for list endpoints
+use std::{future::Future, pin::Pin};
+
+use futures::{stream, Stream, StreamExt as _, TryStreamExt as _};
+
+use crate::{
+    errors::AnthropicError,
+    types::{
+        FileMetadata, ListFilesResponse, ListMessageBatchesResponse, ListModelsResponse,
+        ListParams, MessageBatch, Model,
+    },
+};
+
+/// A single page of a list endpoint
+pub trait Page {
+    type Item;
+
+    fn has_more(&self) -> bool;
+    fn first_id(&self) -> Option<&str>;
+    fn last_id(&self) -> Option<&str>;
+    fn into_items(self) -> Vec<Self::Item>;
+}
+
+pub type PaginatedStream<'c, T> =
+    Pin<Box<dyn Stream<Item = Result<T, AnthropicError>> + Send + 'c>>;
+
+/// Streams the items of every page, following the cursors until there are no more pages
+///
+/// Pages forward from `after_id` by default. If `before_id` is set, pages backwards instead.
+pub fn paginate<'c, P, F, Fut>(params: ListParams, fetch: F) -> PaginatedStream<'c, P::Item>
+where
+    P: Page + Send + 'c,
+    P::Item: Send + 'c,
+    F: Fn(ListParams) -> Fut + Send + 'c,
+    Fut: Future<Output = Result<P, AnthropicError>> + Send + 'c,
+{
+    stream::try_unfold((fetch, Some(params)), |(fetch, params)| async move {
+        let Some(params) = params else {
+            return Ok::<_, AnthropicError>(None);
+        };
+
+        let page = fetch(params.clone()).await?;
+        let next = match (page.has_more(), params.before_id.is_some()) {
+            (false, _) => None,
+            (true, false) => page.last_id().map(|last_id| ListParams {
+                after_id: Some(last_id.to_string()),
+                ..params
+            }),
+            (true, true) => page.first_id().map(|first_id| ListParams {
+                before_id: Some(first_id.to_string()),
+                ..params
+            }),
+        };
+
+        Ok(Some((page.into_items(), (fetch, next))))
+    })
+    .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+    .try_flatten()
+    .boxed()
+}
+
+macro_rules! impl_page {
+    ($response:ty, $item:ty) => {
+        impl Page for $response {
+            type Item = $item;
+
+            fn has_more(&self) -> bool {
+                self.has_more
+            }
+
+            fn first_id(&self) -> Option<&str> {
+                self.first_id.as_deref()
+            }
+
+            fn last_id(&self) -> Option<&str> {
+                self.last_id.as_deref()
+            }
+
+            fn into_items(self) -> Vec<Self::Item> {
+                self.data
+            }
+        }
+    };
+}
+
+impl_page!(ListModelsResponse, Model);
+impl_page!(ListMessageBatchesResponse, MessageBatch);
+impl_page!(ListFilesResponse, FileMetadata);
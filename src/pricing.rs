@@ -0,0 +1,250 @@
+//! Estimate the cost of requests from their token usage
+//!
+//! Prices are in USD per million tokens. The built-in table reflects the public list prices at the
+//! time of release; override or extend it at runtime with [`PricingTable::insert`].
+use std::{
+    collections::HashMap,
+    ops::{Add, AddAssign},
+};
+
+use crate::types::{CreateMessagesResponse, Usage};
+
+/// Prices for a model, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    /// Cache writes with the default 5 minute ttl
+    pub cache_write_5m: f64,
+    pub cache_write_1h: f64,
+    pub cache_read: f64,
+}
+
+impl ModelPricing {
+    /// Derives cache prices from the input price with the standard multipliers
+    pub fn from_input_output(input: f64, output: f64) -> Self {
+        ModelPricing {
+            input,
+            output,
+            cache_write_5m: input * 1.25,
+            cache_write_1h: input * 2.0,
+            cache_read: input * 0.1,
+        }
+    }
+}
+
+/// Maps model ids to their pricing
+///
+/// Models are matched on the longest known prefix, so `claude-sonnet-4-20250514` and
+/// `claude-sonnet-4-5` both resolve to the prices of `claude-sonnet-4` unless they are added
+/// explicitly.
+///
+/// # Example
+///
+/// ```
+/// # use async_anthropic::{pricing::{ModelPricing, PricingTable}, types::Usage};
+/// let mut pricing = PricingTable::default();
+/// pricing.insert("my-fine-tune", ModelPricing::from_input_output(1.0, 2.0));
+///
+/// let usage = Usage {
+///     input_tokens: Some(1_000_000),
+///     output_tokens: Some(500_000),
+///     ..Default::default()
+/// };
+///
+/// let cost = pricing.cost("my-fine-tune", &usage).unwrap();
+/// assert_eq!(cost.total(), 2.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingTable {
+    models: HashMap<String, ModelPricing>,
+    /// Price of a single web search request
+    pub web_search_request: f64,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let priced = ModelPricing::from_input_output;
+        let models = [
+            ("claude-opus-4-5", priced(5.0, 25.0)),
+            ("claude-opus-4", priced(15.0, 75.0)),
+            ("claude-sonnet-4", priced(3.0, 15.0)),
+            ("claude-haiku-4-5", priced(1.0, 5.0)),
+            ("claude-3-7-sonnet", priced(3.0, 15.0)),
+            ("claude-3-5-sonnet", priced(3.0, 15.0)),
+            ("claude-3-5-haiku", priced(0.8, 4.0)),
+            ("claude-3-opus", priced(15.0, 75.0)),
+            (
+                "claude-3-haiku",
+                ModelPricing {
+                    input: 0.25,
+                    output: 1.25,
+                    cache_write_5m: 0.3,
+                    cache_write_1h: 0.5,
+                    cache_read: 0.03,
+                },
+            ),
+        ];
+
+        PricingTable {
+            models: models
+                .into_iter()
+                .map(|(model, pricing)| (model.to_string(), pricing))
+                .collect(),
+            web_search_request: 0.01,
+        }
+    }
+}
+
+impl PricingTable {
+    /// A table without any models
+    pub fn empty() -> Self {
+        PricingTable {
+            models: HashMap::new(),
+            web_search_request: 0.0,
+        }
+    }
+
+    /// Adds or replaces the pricing for a model id or model id prefix
+    pub fn insert(&mut self, model: impl Into<String>, pricing: ModelPricing) -> &mut Self {
+        self.models.insert(model.into(), pricing);
+        self
+    }
+
+    /// Returns the pricing for a model, matching on the longest known prefix
+    pub fn get(&self, model: &str) -> Option<&ModelPricing> {
+        self.models
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, pricing)| pricing)
+    }
+
+    /// Estimates the cost of the usage for a model
+    ///
+    /// Returns `None` if the model is unknown.
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<Cost> {
+        let pricing = self.get(model)?;
+        let per_token = |tokens: u32, price: f64| f64::from(tokens) * price / 1_000_000.0;
+
+        let cache_write = match usage.cache_creation {
+            Some(breakdown) => {
+                per_token(breakdown.ephemeral_5m_input_tokens, pricing.cache_write_5m)
+                    + per_token(breakdown.ephemeral_1h_input_tokens, pricing.cache_write_1h)
+            }
+            None => per_token(
+                usage.cache_creation_input_tokens.unwrap_or_default(),
+                pricing.cache_write_5m,
+            ),
+        };
+
+        Some(Cost {
+            input: per_token(usage.input_tokens.unwrap_or_default(), pricing.input),
+            output: per_token(usage.output_tokens.unwrap_or_default(), pricing.output),
+            cache_write,
+            cache_read: per_token(
+                usage.cache_read_input_tokens.unwrap_or_default(),
+                pricing.cache_read,
+            ),
+            server_tools: usage.server_tool_use.map_or(0.0, |server_tool_use| {
+                f64::from(server_tool_use.web_search_requests) * self.web_search_request
+            }),
+        })
+    }
+
+    /// Estimates the cost of a response, i.e. from `Messages::create` or an accumulated stream
+    ///
+    /// Returns `None` if the response has no model or usage, or the model is unknown.
+    pub fn response_cost(&self, response: &CreateMessagesResponse) -> Option<Cost> {
+        self.cost(response.model.as_deref()?, response.usage.as_ref()?)
+    }
+}
+
+/// Estimated cost in USD, broken down by token type
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Cost {
+    pub input: f64,
+    pub output: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+    pub server_tools: f64,
+}
+
+impl Cost {
+    pub fn total(&self) -> f64 {
+        self.input + self.output + self.cache_write + self.cache_read + self.server_tools
+    }
+}
+
+impl Add for Cost {
+    type Output = Cost;
+
+    fn add(mut self, rhs: Cost) -> Cost {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Cost {
+    fn add_assign(&mut self, rhs: Cost) {
+        self.input += rhs.input;
+        self.output += rhs.output;
+        self.cache_write += rhs.cache_write;
+        self.cache_read += rhs.cache_read;
+        self.server_tools += rhs.server_tools;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{CacheCreation, ServerToolUsage};
+
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_cost_with_cache_and_server_tools() {
+        let pricing = PricingTable::default();
+        let usage = Usage {
+            input_tokens: Some(1_000),
+            output_tokens: Some(2_000),
+            cache_creation_input_tokens: Some(3_000),
+            cache_read_input_tokens: Some(10_000),
+            cache_creation: Some(CacheCreation {
+                ephemeral_5m_input_tokens: 1_000,
+                ephemeral_1h_input_tokens: 2_000,
+            }),
+            server_tool_use: Some(ServerToolUsage {
+                web_search_requests: 3,
+            }),
+            ..Default::default()
+        };
+
+        let cost = pricing.cost("claude-sonnet-4-20250514", &usage).unwrap();
+
+        assert_close(cost.input, 0.003);
+        assert_close(cost.output, 0.03);
+        assert_close(cost.cache_write, 0.00375 + 0.012);
+        assert_close(cost.cache_read, 0.003);
+        assert_close(cost.server_tools, 0.03);
+        assert_close(cost.total(), 0.08175);
+    }
+
+    #[test]
+    fn test_longest_prefix_and_overrides() {
+        let mut pricing = PricingTable::default();
+
+        assert_eq!(pricing.get("claude-opus-4-5-20251101").unwrap().input, 5.0);
+        assert_eq!(pricing.get("claude-opus-4-1-20250805").unwrap().input, 15.0);
+        assert!(pricing.get("gpt-4o").is_none());
+
+        pricing.insert(
+            "claude-opus-4-1",
+            ModelPricing::from_input_output(10.0, 50.0),
+        );
+        assert_eq!(pricing.get("claude-opus-4-1-20250805").unwrap().input, 10.0);
+    }
+}
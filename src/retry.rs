@@ -0,0 +1,407 @@
+//! Retry policies for requests to the api
+//!
+//! Every request made by the [`Client`](crate::Client) consults its [`RetryPolicy`] after a failed
+//! attempt. The [`DefaultRetryPolicy`] retries network failures, rate limits, overloaded and server
+//! errors with a separate backoff for each, while an `ExponentialBackoff` can be used to retry them
+//! all identically.
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher as _, Hasher as _},
+    sync::Arc,
+    time::Duration,
+};
+
+use backoff::{backoff::Backoff as _, ExponentialBackoff};
+
+use crate::errors::{AnthropicError, ApiErrorKind};
+
+/// Decides whether and when to retry a failed attempt
+pub trait RetryPolicy: std::fmt::Debug + Send + Sync {
+    /// Returns the delay before the next attempt, or `None` to give up and return the error
+    fn retry_delay(&self, context: &RetryContext<'_>) -> Option<Duration>;
+
+    /// Called before waiting for the next attempt, i.e. to log or record metrics
+    fn on_retry(&self, _context: &RetryContext<'_>, _delay: Duration) {}
+}
+
+/// A failed attempt
+#[derive(Debug)]
+pub struct RetryContext<'a> {
+    /// Number of attempts made so far, starting at 1
+    pub attempt: u32,
+    pub error: &'a AnthropicError,
+    /// Delay requested by the api, from the `retry-after` or rate limit headers
+    pub retry_after: Option<Duration>,
+    /// Time since the first attempt was started
+    pub elapsed: Duration,
+}
+
+impl RetryContext<'_> {
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::of(self.error)
+    }
+}
+
+/// Categories of errors that can be retried independently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The connection could not be established or was lost
+    Connection,
+    Timeout,
+    /// 429, or a `rate_limit_error` event in a stream
+    RateLimit,
+    /// 529, or an `overloaded_error` event in a stream
+    Overloaded,
+    /// 500, 502, 503 and 504, or an `api_error` event in a stream
+    Server,
+    /// Everything else, i.e. invalid requests
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(error: &AnthropicError) -> Self {
+        match error {
+            AnthropicError::NetworkError(err) if err.is_timeout() => ErrorClass::Timeout,
+            AnthropicError::NetworkError(err) if err.is_connect() || err.is_request() => {
+                ErrorClass::Connection
+            }
+            // The connection was lost while reading the response, i.e. before the first event of
+            // a stream
+            AnthropicError::NetworkError(err)
+                if err.is_body() || (err.is_decode() && is_io_error(err)) =>
+            {
+                ErrorClass::Connection
+            }
+            AnthropicError::ApiError(err) => match err.status {
+                429 => ErrorClass::RateLimit,
+                529 => ErrorClass::Overloaded,
+                500 | 502 | 503 | 504 => ErrorClass::Server,
+                // Errors sent as events after the stream was opened
+                200 => match err.kind {
+                    ApiErrorKind::RateLimit => ErrorClass::RateLimit,
+                    ApiErrorKind::Overloaded => ErrorClass::Overloaded,
+                    ApiErrorKind::Api => ErrorClass::Server,
+                    _ => ErrorClass::Other,
+                },
+                _ => ErrorClass::Other,
+            },
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+fn is_io_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |err| err.source()).any(|err| err.is::<std::io::Error>())
+}
+
+/// Exponentially increasing delays between attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryBackoff {
+    pub initial_interval: Duration,
+    pub multiplier: f64,
+    pub max_interval: Duration,
+    /// Delays are randomized by up to this fraction in either direction
+    pub randomization_factor: f64,
+}
+
+impl RetryBackoff {
+    pub fn new(initial_interval: Duration, max_interval: Duration) -> Self {
+        RetryBackoff {
+            initial_interval,
+            multiplier: 2.0,
+            max_interval,
+            randomization_factor: 0.1,
+        }
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_randomization_factor(mut self, randomization_factor: f64) -> Self {
+        self.randomization_factor = randomization_factor;
+        self
+    }
+
+    /// The delay after the given attempt, starting at 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+        let interval = (self.initial_interval.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_interval.as_secs_f64());
+
+        let jitter = self.randomization_factor * (2.0 * random_fraction() - 1.0);
+        Duration::try_from_secs_f64(interval * (1.0 + jitter)).unwrap_or(self.max_interval)
+    }
+}
+
+type OnRetry = Arc<dyn Fn(&RetryContext<'_>, Duration) + Send + Sync>;
+
+/// Retries with a separate backoff per [`ErrorClass`], up to a maximum number of attempts
+///
+/// Delays requested by the api take precedence over the backoff. By default:
+///
+/// - connection errors and timeouts are retried after 1s, up to 8s
+/// - server errors are retried after 5s, up to 30s
+/// - rate limit and overloaded errors are retried after 15s, up to 60s
+/// - at most 5 attempts are made, within 2 minutes
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use async_anthropic::{retry::{DefaultRetryPolicy, ErrorClass, RetryBackoff}, Client};
+/// let policy = DefaultRetryPolicy::default()
+///     .with_max_attempts(10)
+///     .with_backoff(
+///         ErrorClass::Overloaded,
+///         RetryBackoff::new(Duration::from_secs(30), Duration::from_secs(300)),
+///     )
+///     .without_retries(ErrorClass::Timeout)
+///     .with_on_retry(|context, delay| {
+///         eprintln!("attempt {} failed, retrying in {delay:?}", context.attempt);
+///     });
+///
+/// let client = Client::default().with_retry_policy(policy);
+/// ```
+#[derive(Clone)]
+pub struct DefaultRetryPolicy {
+    max_attempts: u32,
+    max_elapsed_time: Option<Duration>,
+    backoffs: HashMap<ErrorClass, RetryBackoff>,
+    on_retry: Option<OnRetry>,
+}
+
+impl Default for DefaultRetryPolicy {
+    fn default() -> Self {
+        let network = RetryBackoff::new(Duration::from_secs(1), Duration::from_secs(8));
+        let server = RetryBackoff::new(Duration::from_secs(5), Duration::from_secs(30));
+        let overloaded = RetryBackoff::new(Duration::from_secs(15), Duration::from_secs(60));
+
+        DefaultRetryPolicy {
+            max_attempts: 5,
+            max_elapsed_time: Some(Duration::from_secs(120)),
+            backoffs: HashMap::from([
+                (ErrorClass::Connection, network),
+                (ErrorClass::Timeout, network),
+                (ErrorClass::Server, server),
+                (ErrorClass::RateLimit, overloaded),
+                (ErrorClass::Overloaded, overloaded),
+            ]),
+            on_retry: None,
+        }
+    }
+}
+
+impl DefaultRetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        DefaultRetryPolicy {
+            max_attempts: 1,
+            max_elapsed_time: None,
+            backoffs: HashMap::new(),
+            on_retry: None,
+        }
+    }
+
+    /// Maximum number of attempts, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Stops retrying once this much time has passed since the first attempt
+    pub fn with_max_elapsed_time(mut self, max_elapsed_time: Option<Duration>) -> Self {
+        self.max_elapsed_time = max_elapsed_time;
+        self
+    }
+
+    /// Retries errors of the class with the backoff
+    pub fn with_backoff(mut self, class: ErrorClass, backoff: RetryBackoff) -> Self {
+        self.backoffs.insert(class, backoff);
+        self
+    }
+
+    /// Never retries errors of the class
+    pub fn without_retries(mut self, class: ErrorClass) -> Self {
+        self.backoffs.remove(&class);
+        self
+    }
+
+    /// Calls the function before every retry
+    pub fn with_on_retry<F>(mut self, on_retry: F) -> Self
+    where
+        F: Fn(&RetryContext<'_>, Duration) + Send + Sync + 'static,
+    {
+        self.on_retry = Some(Arc::new(on_retry));
+        self
+    }
+}
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn retry_delay(&self, context: &RetryContext<'_>) -> Option<Duration> {
+        if context.attempt >= self.max_attempts {
+            return None;
+        }
+
+        let backoff = self.backoffs.get(&context.class())?;
+        let delay = context
+            .retry_after
+            .unwrap_or_else(|| backoff.delay(context.attempt));
+
+        match self.max_elapsed_time {
+            Some(max_elapsed_time) if context.elapsed + delay > max_elapsed_time => None,
+            _ => Some(delay),
+        }
+    }
+
+    fn on_retry(&self, context: &RetryContext<'_>, delay: Duration) {
+        if let Some(on_retry) = &self.on_retry {
+            on_retry(context, delay);
+        }
+    }
+}
+
+impl std::fmt::Debug for DefaultRetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DefaultRetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("max_elapsed_time", &self.max_elapsed_time)
+            .field("backoffs", &self.backoffs)
+            .field("on_retry", &self.on_retry.is_some())
+            .finish()
+    }
+}
+
+/// Retries every transient error with the same backoff, until its maximum elapsed time
+impl RetryPolicy for ExponentialBackoff {
+    fn retry_delay(&self, context: &RetryContext<'_>) -> Option<Duration> {
+        if context.class() == ErrorClass::Other {
+            return None;
+        }
+
+        if let Some(max_elapsed_time) = self.max_elapsed_time {
+            if context.elapsed >= max_elapsed_time {
+                return None;
+            }
+        }
+
+        let mut backoff = self.clone();
+        backoff.max_elapsed_time = None;
+        backoff.reset();
+        for _ in 1..context.attempt {
+            backoff.next_backoff();
+        }
+
+        context.retry_after.or_else(|| backoff.next_backoff())
+    }
+}
+
+// A random number in [0, 1), without depending on a random number generator
+fn random_fraction() -> f64 {
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ApiError;
+
+    use super::*;
+
+    fn api_error(status: u16) -> AnthropicError {
+        AnthropicError::ApiError(ApiError::from_response(status, None, ""))
+    }
+
+    fn context(attempt: u32, error: &AnthropicError) -> RetryContext<'_> {
+        RetryContext {
+            attempt,
+            error,
+            retry_after: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_default_policy_per_error_class() {
+        let policy = DefaultRetryPolicy::default()
+            .with_backoff(
+                ErrorClass::Server,
+                RetryBackoff::new(Duration::from_secs(1), Duration::from_secs(3))
+                    .with_randomization_factor(0.0),
+            )
+            .with_max_attempts(4);
+
+        let server = api_error(503);
+        let delays = (1..=4)
+            .map(|attempt| policy.retry_delay(&context(attempt, &server)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(3)),
+                None
+            ]
+        );
+
+        let bad_request = api_error(400);
+        assert_eq!(policy.retry_delay(&context(1, &bad_request)), None);
+
+        let rate_limited = api_error(429);
+        let mut context = context(1, &rate_limited);
+        context.retry_after = Some(Duration::from_millis(500));
+        assert_eq!(
+            policy.retry_delay(&context),
+            Some(Duration::from_millis(500))
+        );
+
+        context.elapsed = Duration::from_secs(120);
+        assert_eq!(policy.retry_delay(&context), None);
+
+        let policy = policy.without_retries(ErrorClass::RateLimit);
+        context.elapsed = Duration::ZERO;
+        assert_eq!(policy.retry_delay(&context), None);
+    }
+
+    #[test]
+    fn test_classifies_stream_error_events() {
+        let event = |kind: &str| {
+            let body =
+                format!(r#"{{"type": "error", "error": {{"type": "{kind}", "message": ""}}}}"#);
+            AnthropicError::ApiError(ApiError::from_response(200, None, &body))
+        };
+
+        assert_eq!(
+            ErrorClass::of(&event("overloaded_error")),
+            ErrorClass::Overloaded
+        );
+        assert_eq!(
+            ErrorClass::of(&event("rate_limit_error")),
+            ErrorClass::RateLimit
+        );
+        assert_eq!(ErrorClass::of(&event("api_error")), ErrorClass::Server);
+        assert_eq!(
+            ErrorClass::of(&event("invalid_request_error")),
+            ErrorClass::Other
+        );
+        assert_eq!(ErrorClass::of(&api_error(200)), ErrorClass::Other);
+    }
+
+    #[test]
+    fn test_backoff_randomization() {
+        let backoff = RetryBackoff::new(Duration::from_secs(10), Duration::from_secs(60));
+
+        for attempt in 1..=5 {
+            let expected = (10.0 * 2f64.powi(attempt - 1)).min(60.0);
+            let delay = backoff.delay(attempt as u32).as_secs_f64();
+            assert!(
+                delay >= expected * 0.9 && delay <= expected * 1.1,
+                "{delay} not within 10% of {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Helpers for working with streamed message responses
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use tokio_stream::{Stream, StreamExt as _};
+
+use crate::{
+    errors::{AnthropicError, StreamError},
+    types::{
+        ContentBlockDelta, CreateMessagesResponse, MessageContent, MessagesStreamEvent, Usage,
+    },
+};
+
+mod partial_json;
+
+pub use partial_json::{parse_partial_json, PartialJsonParser};
+
+/// Folds streamed events into a complete `CreateMessagesResponse`
+///
+/// Text and thinking deltas are appended to their content block, and the partial json of tool uses is
+/// buffered until the block stops, after which it is parsed into `ToolUse::input`. The same applies
+/// to unknown blocks with an `input`, like server tool uses. Other unknown events and deltas are
+/// skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// # use async_anthropic::{stream::StreamAccumulator, types::*};
+/// # use tokio_stream::StreamExt as _;
+/// # async fn run(request: CreateMessagesRequest) -> Result<(), async_anthropic::errors::AnthropicError> {
+/// let client = async_anthropic::Client::default();
+/// let mut stream = client.messages().create_stream(request).await;
+///
+/// let mut accumulator = StreamAccumulator::default();
+/// while let Some(event) = stream.next().await {
+///     let event = event?;
+///     // Render the event as it comes in ...
+///     accumulator.push(&event)?;
+/// }
+///
+/// let response = accumulator.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamAccumulator {
+    response: Option<CreateMessagesResponse>,
+    content: BTreeMap<usize, MessageContent>,
+    partial_json: BTreeMap<usize, String>,
+}
+
+impl StreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a single stream event
+    pub fn push(&mut self, event: &MessagesStreamEvent) -> Result<(), AnthropicError> {
+        match event {
+            MessagesStreamEvent::MessageStart { message, usage } => {
+                self.content
+                    .extend(message.content.iter().cloned().enumerate());
+                self.response = Some(CreateMessagesResponse {
+                    id: Some(message.id.clone()),
+                    content: None,
+                    model: Some(message.model.clone()),
+                    stop_reason: message.stop_reason.clone(),
+                    stop_sequence: message.stop_sequence.clone(),
+                    usage: message.usage.clone().or_else(|| usage.clone()),
+                });
+            }
+            MessagesStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                self.content.insert(*index, content_block.clone());
+            }
+            MessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                match (self.content.get_mut(index), delta) {
+                    (
+                        Some(MessageContent::Text(text)),
+                        ContentBlockDelta::TextDelta { text: t },
+                    ) => {
+                        text.text.push_str(t);
+                    }
+                    (
+                        Some(MessageContent::Thinking(thinking)),
+                        ContentBlockDelta::ThinkingDelta { thinking: t },
+                    ) => {
+                        thinking.thinking.push_str(t);
+                    }
+                    (
+                        Some(MessageContent::Thinking(thinking)),
+                        ContentBlockDelta::SignatureDelta { signature },
+                    ) => {
+                        thinking.signature.push_str(signature);
+                    }
+                    (
+                        Some(MessageContent::ToolUse(_)),
+                        ContentBlockDelta::InputJsonDelta { partial_json },
+                    ) => {
+                        self.partial_json
+                            .entry(*index)
+                            .or_default()
+                            .push_str(partial_json);
+                    }
+                    // Server tool uses stream their input like tool uses
+                    (
+                        Some(MessageContent::Unknown(block)),
+                        ContentBlockDelta::InputJsonDelta { partial_json },
+                    ) if block.get("input").is_some() => {
+                        self.partial_json
+                            .entry(*index)
+                            .or_default()
+                            .push_str(partial_json);
+                    }
+                    // Deltas this crate does not know how to apply yet
+                    (Some(MessageContent::Unknown(_)), _) | (_, ContentBlockDelta::Unknown(_)) => {
+                        tracing::debug!("Skipping unsupported delta for content block {index}");
+                    }
+                    (block, delta) => {
+                        return Err(invalid_stream(format!(
+                            "unexpected delta {delta:?} for content block {index}: {block:?}"
+                        )))
+                    }
+                }
+            }
+            MessagesStreamEvent::ContentBlockStop { index } => self.finish_block(*index)?,
+            MessagesStreamEvent::MessageDelta { delta, usage } => {
+                let response = self.response.get_or_insert_with(empty_response);
+                if delta.stop_reason.is_some() {
+                    response.stop_reason.clone_from(&delta.stop_reason);
+                }
+                if delta.stop_sequence.is_some() {
+                    response.stop_sequence.clone_from(&delta.stop_sequence);
+                }
+                if let Some(usage) = usage {
+                    response
+                        .usage
+                        .get_or_insert_with(Usage::default)
+                        .merge(usage);
+                }
+            }
+            MessagesStreamEvent::MessageStop | MessagesStreamEvent::Unknown(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Best effort parse of the tool input streamed so far for the content block at `index`
+    ///
+    /// Useful to render tool arguments while they are being generated.
+    pub fn partial_input(&self, index: usize) -> Option<Value> {
+        let input = match self.content.get(&index) {
+            Some(MessageContent::ToolUse(tool_use)) => &tool_use.input,
+            Some(MessageContent::Unknown(block)) => block.get("input")?,
+            _ => return None,
+        };
+
+        match self.partial_json.get(&index) {
+            Some(json) => parse_partial_json(json),
+            None => Some(input.clone()),
+        }
+    }
+
+    /// Returns the accumulated response
+    ///
+    /// Tool uses that never received a `content_block_stop` are parsed as well.
+    pub fn finish(mut self) -> Result<CreateMessagesResponse, AnthropicError> {
+        let pending = self.partial_json.keys().copied().collect::<Vec<_>>();
+        for index in pending {
+            self.finish_block(index)?;
+        }
+
+        let mut response = self.response.unwrap_or_else(empty_response);
+        response.content = Some(self.content.into_values().collect());
+
+        Ok(response)
+    }
+
+    fn finish_block(&mut self, index: usize) -> Result<(), AnthropicError> {
+        let Some(json) = self.partial_json.remove(&index) else {
+            return Ok(());
+        };
+
+        let input = match self.content.get_mut(&index) {
+            Some(MessageContent::ToolUse(tool_use)) => &mut tool_use.input,
+            Some(MessageContent::Unknown(block)) => match block.get_mut("input") {
+                Some(input) => input,
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+
+        // Tools without parameters stream an empty string
+        if !json.trim().is_empty() {
+            *input = serde_json::from_str(&json)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Consumes a stream of events into a complete `CreateMessagesResponse`
+///
+/// Returns the first error encountered in the stream.
+pub async fn accumulate<S>(mut stream: S) -> Result<CreateMessagesResponse, AnthropicError>
+where
+    S: Stream<Item = Result<MessagesStreamEvent, AnthropicError>> + Unpin,
+{
+    let mut accumulator = StreamAccumulator::new();
+
+    while let Some(event) = stream.next().await {
+        accumulator.push(&event?)?;
+    }
+
+    accumulator.finish()
+}
+
+fn empty_response() -> CreateMessagesResponse {
+    CreateMessagesResponse {
+        id: None,
+        content: None,
+        model: None,
+        stop_reason: None,
+        stop_sequence: None,
+        usage: None,
+    }
+}
+
+fn invalid_stream(message: String) -> AnthropicError {
+    AnthropicError::StreamError(StreamError {
+        error_type: "invalid_stream".to_string(),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::{StopReason, Thinking};
+
+    #[tokio::test]
+    async fn test_accumulate_text_and_tool_use() {
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": [], "usage": {"input_tokens": 10, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "check."}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "citations_delta", "citation": {}}}),
+            json!({"type": "message_annotation", "data": {}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"location\": "}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Amsterdam\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use", "stop_sequence": null}, "usage": {"output_tokens": 42}}),
+            json!({"type": "message_stop"}),
+        ]
+        .map(|event| Ok(serde_json::from_value::<MessagesStreamEvent>(event).unwrap()));
+
+        let response = accumulate(tokio_stream::iter(events)).await.unwrap();
+
+        assert_eq!(response.id.as_deref(), Some("msg_1"));
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+
+        let usage = response.usage.as_ref().unwrap();
+        assert_eq!(usage.input_tokens, Some(10));
+        assert_eq!(usage.output_tokens, Some(42));
+
+        let message = response.message();
+        assert_eq!(message.text(), Some("Let me check.".to_string()));
+
+        let tool_uses = message.tool_uses();
+        assert_eq!(tool_uses[0].name, "get_weather");
+        assert_eq!(tool_uses[0].input, json!({"location": "Amsterdam"}));
+    }
+
+    #[tokio::test]
+    async fn test_accumulate_server_tool_use() {
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"query\": "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "\"weather\"}"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "message_stop"}),
+        ]
+        .map(|event| serde_json::from_value::<MessagesStreamEvent>(event).unwrap());
+
+        let mut accumulator = StreamAccumulator::new();
+        for event in &events[..3] {
+            accumulator.push(event).unwrap();
+        }
+        assert_eq!(accumulator.partial_input(0), Some(json!({})));
+
+        for event in &events[3..] {
+            accumulator.push(event).unwrap();
+        }
+        let content = accumulator.finish().unwrap().content.unwrap();
+
+        assert_eq!(
+            content[0],
+            MessageContent::Unknown(json!({
+                "type": "server_tool_use",
+                "id": "srvtoolu_1",
+                "name": "web_search",
+                "input": {"query": "weather"}
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accumulate_thinking() {
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "model": "claude", "role": "assistant", "content": []}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me think"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": " about it."}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "EqQBCgIYAhIM"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "redacted_thinking", "data": "EmwKAhgBEgy"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_stop"}),
+        ]
+        .map(|event| Ok(serde_json::from_value::<MessagesStreamEvent>(event).unwrap()));
+
+        let response = accumulate(tokio_stream::iter(events)).await.unwrap();
+        let content = response.content.unwrap();
+
+        assert_eq!(
+            content[0].as_thinking(),
+            Some(&Thinking {
+                thinking: "Let me think about it.".to_string(),
+                signature: "EqQBCgIYAhIM".to_string(),
+            })
+        );
+        assert_eq!(
+            content[1].as_redacted_thinking().map(|r| r.data.as_str()),
+            Some("EmwKAhgBEgy")
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use serde_json::Value;
+
+/// Parses incomplete json into a best effort value
+///
+/// Open strings, arrays and objects are closed. Trailing tokens that cannot be completed, like a
+/// dangling object key or a partial literal, are dropped. Returns `None` if nothing can be parsed
+/// yet.
+///
+/// # Example
+///
+/// ```
+/// # use async_anthropic::stream::parse_partial_json;
+/// # use serde_json::json;
+/// assert_eq!(
+///     parse_partial_json(r#"{"location": "San Fra"#),
+///     Some(json!({"location": "San Fra"}))
+/// );
+/// assert_eq!(
+///     parse_partial_json(r#"{"location": "San Francisco", "un"#),
+///     Some(json!({"location": "San Francisco"}))
+/// );
+/// ```
+pub fn parse_partial_json(json: &str) -> Option<Value> {
+    let mut scan = Scan::default();
+    scan.push(0, json);
+    scan.value(json)
+}
+
+/// Accumulates json fragments, i.e. from `ContentBlockDelta::InputJsonDelta`, and parses the
+/// running buffer after every fragment
+///
+/// Every fragment is scanned once; the state of open strings and containers is kept between
+/// fragments.
+#[derive(Debug, Clone, Default)]
+pub struct PartialJsonParser {
+    buffer: String,
+    scan: Scan,
+}
+
+impl PartialJsonParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a fragment and returns the best effort value of everything received so far
+    pub fn push(&mut self, fragment: &str) -> Option<Value> {
+        self.scan.push(self.buffer.len(), fragment);
+        self.buffer.push_str(fragment);
+        self.value()
+    }
+
+    pub fn value(&self) -> Option<Value> {
+        self.scan.value(&self.buffer)
+    }
+
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scan {
+    // Open containers, as their closing character
+    stack: Vec<char>,
+    in_string: bool,
+    escaped: bool,
+    // Offsets right after an opening bracket or right before a comma, with the containers open at
+    // that offset
+    cuts: Vec<(usize, Vec<char>)>,
+}
+
+impl Scan {
+    /// Scans a fragment starting at `offset` in the json
+    fn push(&mut self, offset: usize, fragment: &str) {
+        for (i, c) in fragment.char_indices() {
+            let i = offset + i;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == '\\' {
+                    self.escaped = true;
+                } else if c == '"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match c {
+                '"' => self.in_string = true,
+                '{' => {
+                    self.stack.push('}');
+                    self.cuts.push((i + 1, self.stack.clone()));
+                }
+                '[' => {
+                    self.stack.push(']');
+                    self.cuts.push((i + 1, self.stack.clone()));
+                }
+                '}' | ']' => {
+                    self.stack.pop();
+                    // Cuts inside the closed container are superseded by the container itself
+                    while self
+                        .cuts
+                        .last()
+                        .is_some_and(|(_, stack)| stack.len() > self.stack.len())
+                    {
+                        self.cuts.pop();
+                    }
+                }
+                ',' => self.cuts.push((i, self.stack.clone())),
+                _ => {}
+            }
+        }
+    }
+
+    fn value(&self, json: &str) -> Option<Value> {
+        if let Some(value) = self.complete(json) {
+            return Some(value);
+        }
+
+        // Fall back to the last point where the json was structurally sound
+        self.cuts.iter().rev().find_map(|(cut, stack)| {
+            let mut repaired = json[..*cut].to_string();
+            repaired.extend(stack.iter().rev());
+            serde_json::from_str(&repaired).ok()
+        })
+    }
+
+    fn complete(&self, json: &str) -> Option<Value> {
+        let mut repaired = json.to_string();
+
+        if self.in_string {
+            if self.escaped {
+                repaired.pop();
+            } else if let Some(pos) = repaired.rfind("\\u") {
+                // Drop an incomplete unicode escape
+                if repaired.len() - pos < 6 && !is_escaped(&repaired[..pos]) {
+                    repaired.truncate(pos);
+                }
+            }
+            repaired.push('"');
+        } else {
+            let trimmed = repaired.trim_end().len();
+            repaired.truncate(trimmed);
+            // Incomplete numbers, i.e. `1.`, `1e` or `-`, but not literals like `true`
+            let is_number_char =
+                |c: char| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E');
+            let token = repaired
+                .rfind(|c: char| !is_number_char(c))
+                .map_or(0, |i| i + 1);
+            if repaired[token..].starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                while repaired.ends_with(['.', '-', '+', 'e', 'E']) {
+                    repaired.pop();
+                }
+            }
+        }
+
+        repaired.extend(self.stack.iter().rev());
+
+        if repaired.trim().is_empty() {
+            return None;
+        }
+
+        serde_json::from_str(&repaired).ok()
+    }
+}
+
+// True if the string ends in an odd number of backslashes
+fn is_escaped(s: &str) -> bool {
+    s.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_partial_json() {
+        let cases = [
+            ("", None),
+            ("{", Some(json!({}))),
+            (r#"{"loc"#, Some(json!({}))),
+            (r#"{"location""#, Some(json!({}))),
+            (r#"{"location":"#, Some(json!({}))),
+            (r#"{"location": "Ams"#, Some(json!({"location": "Ams"}))),
+            (r#"{"location": "A\"#, Some(json!({"location": "A"}))),
+            (r#"{"location": "A\u00"#, Some(json!({"location": "A"}))),
+            (r#"{"a": 1, "b": tr"#, Some(json!({"a": 1}))),
+            (r#"{"a": 1.5, "b": -"#, Some(json!({"a": 1.5}))),
+            (r#"{"a": 12"#, Some(json!({"a": 12}))),
+            (r#"{"a": 1.5e"#, Some(json!({"a": 1.5}))),
+            (r#"{"a": true"#, Some(json!({"a": true}))),
+            (r#"{"a": false"#, Some(json!({"a": false}))),
+            (r#"{"a": 1, "b": false"#, Some(json!({"a": 1, "b": false}))),
+            (r#"{"a": tru"#, Some(json!({}))),
+            ("[true", Some(json!([true]))),
+            (
+                r#"{"a": [1, 2, {"b": "c"#,
+                Some(json!({"a": [1, 2, {"b": "c"}]})),
+            ),
+            (r#"{"a": [1, 2,"#, Some(json!({"a": [1, 2]}))),
+            (r#"{"a": {"b": 1}, "c"#, Some(json!({"a": {"b": 1}}))),
+            (r#"["x", "y"#, Some(json!(["x", "y"]))),
+            (r#"{"a": "b"}"#, Some(json!({"a": "b"}))),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse_partial_json(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_partial_json_parser() {
+        let mut parser = PartialJsonParser::new();
+
+        assert_eq!(parser.push(r#"{"locat"#), Some(json!({})));
+        assert_eq!(
+            parser.push(r#"ion": "San "#),
+            Some(json!({"location": "San "}))
+        );
+        assert_eq!(
+            parser.push(r#"Francisco"}"#),
+            Some(json!({"location": "San Francisco"}))
+        );
+    }
+
+    #[test]
+    fn test_partial_json_parser_matches_full_parse() {
+        let json = r#"{"a": [1, 2.5, {"b": "c\"dé"}], "e": {"f": null}, "g": true}"#;
+        let mut parser = PartialJsonParser::new();
+
+        for (i, c) in json.char_indices() {
+            let prefix = &json[..i + c.len_utf8()];
+            assert_eq!(
+                parser.push(&c.to_string()),
+                parse_partial_json(prefix),
+                "prefix: {prefix}"
+            );
+        }
+
+        assert_eq!(
+            parser.value(),
+            Some(json!({"a": [1, 2.5, {"b": "c\"d\u{e9}"}], "e": {"f": null}, "g": true}))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap, fmt::Display, future::Future, pin::Pin, sync::Arc, time::Duration,
+};
+
+use futures::{stream, StreamExt as _};
+
+use crate::{
+    errors::AnthropicError,
+    types::{
+        CreateMessagesRequest, CreateMessagesResponse, Message, MessageContent, MessageContentList,
+        MessageRole, StopReason, ToolResult, ToolUse, Usage,
+    },
+    Client,
+};
+
+pub const DEFAULT_MAX_ITERATIONS: usize = 10;
+pub const DEFAULT_MAX_PARALLELISM: usize = 4;
+
+type ToolFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+type ToolHandler = Arc<dyn Fn(ToolUse) -> ToolFuture + Send + Sync>;
+
+/// Runs a conversation with tools until the model stops requesting them
+///
+/// Every tool use returned by the model is executed with the handler registered under its name.
+/// Tool uses from the same turn run concurrently, up to the configured parallelism. The results
+/// are sent back as a single user message, in the order the tool uses were requested.
+/// Handlers returning an error, and tool uses without a handler, are reported to the model with
+/// `is_error` set.
+///
+/// # Example
+///
+/// ```no_run
+/// # use async_anthropic::{tool_runner::ToolRunner, types::*};
+/// # async fn run(request: CreateMessagesRequest) {
+/// let client = async_anthropic::Client::default();
+///
+/// let output = ToolRunner::new(&client)
+///     .with_tool("get_weather", |tool_use: ToolUse| async move {
+///         Ok::<_, String>(format!("Pretty warm in {}", tool_use.input["location"]))
+///     })
+///     .run(request)
+///     .await
+///     .unwrap();
+///
+/// println!("{:?}", output.response.message().text());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ToolRunner<'c> {
+    client: &'c Client,
+    tools: HashMap<String, ToolHandler>,
+    max_iterations: usize,
+    max_parallelism: usize,
+    tool_timeout: Option<Duration>,
+}
+
+/// The result of a tool run
+#[derive(Debug, Clone)]
+pub struct ToolRunOutput {
+    /// The last response from the model
+    pub response: CreateMessagesResponse,
+    /// The full conversation, including the last assistant message
+    pub messages: Vec<Message>,
+    /// Number of requests made to the messages api
+    pub iterations: usize,
+    /// Usage summed over all requests
+    pub usage: Usage,
+    /// True if the run stopped because the iteration cap was reached while the model still
+    /// requested tools
+    pub max_iterations_reached: bool,
+}
+
+impl std::fmt::Debug for ToolRunner<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolRunner")
+            .field("tools", &self.tools.keys().collect::<Vec<_>>())
+            .field("max_iterations", &self.max_iterations)
+            .field("max_parallelism", &self.max_parallelism)
+            .field("tool_timeout", &self.tool_timeout)
+            .finish()
+    }
+}
+
+impl<'c> ToolRunner<'c> {
+    pub fn new(client: &'c Client) -> Self {
+        ToolRunner {
+            client,
+            tools: HashMap::new(),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_parallelism: DEFAULT_MAX_PARALLELISM,
+            tool_timeout: None,
+        }
+    }
+
+    /// Registers a handler for the tool with the given name
+    pub fn with_tool<F, Fut, E>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(ToolUse) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: Display,
+    {
+        let handler: ToolHandler = Arc::new(move |tool_use| {
+            let fut = handler(tool_use);
+            Box::pin(async move { fut.await.map_err(|e| e.to_string()) })
+        });
+        self.tools.insert(name.into(), handler);
+        self
+    }
+
+    /// Maximum number of requests made to the messages api
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Maximum number of tools executed concurrently within a single turn
+    pub fn with_max_parallelism(mut self, max_parallelism: usize) -> Self {
+        self.max_parallelism = max_parallelism.max(1);
+        self
+    }
+
+    /// Fails a tool use if its handler does not finish in time
+    pub fn with_tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeout = Some(timeout);
+        self
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn run(
+        &self,
+        request: impl Into<CreateMessagesRequest>,
+    ) -> Result<ToolRunOutput, AnthropicError> {
+        let mut request = request.into();
+        let mut iterations = 0;
+        let mut usage = Usage::default();
+
+        loop {
+            let response = self.client.messages().create(request.clone()).await?;
+            iterations += 1;
+            if let Some(response_usage) = &response.usage {
+                usage += response_usage.clone();
+            }
+
+            let message = response.message();
+            let tool_uses = message.tool_uses();
+            request.messages.push(message);
+
+            let wants_tools = response
+                .stop_reason
+                .as_ref()
+                .is_some_and(StopReason::needs_tool_execution);
+            if !wants_tools || tool_uses.is_empty() || iterations >= self.max_iterations {
+                return Ok(ToolRunOutput {
+                    response,
+                    messages: request.messages,
+                    iterations,
+                    usage,
+                    max_iterations_reached: wants_tools && !tool_uses.is_empty(),
+                });
+            }
+
+            // `buffered` yields in submission order, regardless of completion order
+            let results = stream::iter(tool_uses)
+                .map(|tool_use| self.execute(tool_use))
+                .buffered(self.max_parallelism)
+                .collect::<Vec<_>>()
+                .await;
+
+            request.messages.push(Message {
+                role: MessageRole::User,
+                content: MessageContentList(results),
+            });
+        }
+    }
+
+    async fn execute(&self, tool_use: ToolUse) -> MessageContent {
+        let tool_use_id = tool_use.id.clone();
+
+        let result = match (self.tools.get(&tool_use.name), self.tool_timeout) {
+            (Some(handler), Some(timeout)) => tokio::time::timeout(timeout, handler(tool_use))
+                .await
+                .unwrap_or_else(|_| Err(format!("tool timed out after {timeout:?}"))),
+            (Some(handler), None) => handler(tool_use).await,
+            (None, _) => Err(format!("unknown tool: {}", tool_use.name)),
+        };
+
+        if let Err(e) = &result {
+            tracing::warn!(tool_use_id, "Tool failed: {e}");
+        }
+
+        let (content, is_error) = match result {
+            Ok(content) => (content, false),
+            Err(e) => (e, true),
+        };
+
+        ToolResult {
+            tool_use_id,
+            content: Some(content),
+            is_error,
+            cache_control: None,
+        }
+        .into()
+    }
+}
@@ -1,19 +1,145 @@
 use std::{
-    ops::{Deref, DerefMut},
+    ops::{Add, AddAssign, Deref, DerefMut},
+    path::Path,
     pin::Pin,
 };
 
+use base64::{prelude::BASE64_STANDARD, Engine as _};
 use derive_builder::Builder;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use serde_json::Value;
 use tokio_stream::Stream;
 
-use crate::{errors::AnthropicError, messages};
+use crate::{
+    errors::{AnthropicError, ErrorResponse},
+    messages,
+};
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
 pub struct Usage {
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
+    /// Breakdown of `cache_creation_input_tokens` by ttl
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation: Option<CacheCreation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_tool_use: Option<ServerToolUsage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct CacheCreation {
+    #[serde(default)]
+    pub ephemeral_5m_input_tokens: u32,
+    #[serde(default)]
+    pub ephemeral_1h_input_tokens: u32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct ServerToolUsage {
+    #[serde(default)]
+    pub web_search_requests: u32,
+}
+
+impl Usage {
+    /// Updates the usage with the fields present in `other`
+    ///
+    /// Streaming reports cumulative counts; `message_start` carries the input usage and every
+    /// `message_delta` the latest totals. Merging them in order yields the final usage.
+    pub fn merge(&mut self, other: &Usage) {
+        fn merge_field<T: Clone>(field: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                field.clone_from(other);
+            }
+        }
+
+        merge_field(&mut self.input_tokens, &other.input_tokens);
+        merge_field(&mut self.output_tokens, &other.output_tokens);
+        merge_field(
+            &mut self.cache_creation_input_tokens,
+            &other.cache_creation_input_tokens,
+        );
+        merge_field(
+            &mut self.cache_read_input_tokens,
+            &other.cache_read_input_tokens,
+        );
+        merge_field(&mut self.cache_creation, &other.cache_creation);
+        merge_field(&mut self.server_tool_use, &other.server_tool_use);
+        merge_field(&mut self.service_tier, &other.service_tier);
+    }
+
+    /// Input tokens including cache writes and reads
+    pub fn total_input_tokens(&self) -> u32 {
+        self.input_tokens.unwrap_or_default()
+            + self.cache_creation_input_tokens.unwrap_or_default()
+            + self.cache_read_input_tokens.unwrap_or_default()
+    }
+}
+
+/// Sums the usage of separate requests
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(mut self, rhs: Usage) -> Usage {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, rhs: Usage) {
+        fn add<T: Add<Output = T>>(lhs: Option<T>, rhs: Option<T>) -> Option<T> {
+            match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) => Some(lhs + rhs),
+                (lhs, rhs) => lhs.or(rhs),
+            }
+        }
+
+        self.input_tokens = add(self.input_tokens, rhs.input_tokens);
+        self.output_tokens = add(self.output_tokens, rhs.output_tokens);
+        self.cache_creation_input_tokens = add(
+            self.cache_creation_input_tokens,
+            rhs.cache_creation_input_tokens,
+        );
+        self.cache_read_input_tokens =
+            add(self.cache_read_input_tokens, rhs.cache_read_input_tokens);
+        self.cache_creation = add(self.cache_creation, rhs.cache_creation);
+        self.server_tool_use = add(self.server_tool_use, rhs.server_tool_use);
+        if rhs.service_tier.is_some() {
+            self.service_tier = rhs.service_tier;
+        }
+    }
+}
+
+impl Add for CacheCreation {
+    type Output = CacheCreation;
+
+    fn add(self, rhs: CacheCreation) -> CacheCreation {
+        CacheCreation {
+            ephemeral_5m_input_tokens: self.ephemeral_5m_input_tokens
+                + rhs.ephemeral_5m_input_tokens,
+            ephemeral_1h_input_tokens: self.ephemeral_1h_input_tokens
+                + rhs.ephemeral_1h_input_tokens,
+        }
+    }
+}
+
+impl Add for ServerToolUsage {
+    type Output = ServerToolUsage;
+
+    fn add(self, rhs: ServerToolUsage) -> ServerToolUsage {
+        ServerToolUsage {
+            web_search_requests: self.web_search_requests + rhs.web_search_requests,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -103,10 +229,9 @@ pub struct CreateMessagesRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default)]
     pub tool_choice: Option<ToolChoice>,
-    // TODO: Type this
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default)]
-    pub tools: Option<Vec<serde_json::Map<String, Value>>>,
+    pub tools: Option<Vec<ToolDefinition>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default)]
     pub top_k: Option<u32>, // > 0
@@ -115,7 +240,120 @@ pub struct CreateMessagesRequest {
     pub top_p: Option<f32>, // 0 < x < 1
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default)]
-    pub system: Option<String>, // 0 < x < 1
+    pub system: Option<SystemPrompt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub thinking: Option<ThinkingConfig>,
+}
+
+/// The system prompt, either plain text or a list of text blocks
+///
+/// Use blocks to set cache breakpoints on (parts of) the system prompt.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum SystemPrompt {
+    Text(String),
+    Blocks(Vec<SystemBlock>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SystemBlock {
+    Text(Text),
+}
+
+impl From<&str> for SystemPrompt {
+    fn from(s: &str) -> Self {
+        SystemPrompt::Text(s.to_string())
+    }
+}
+
+impl From<String> for SystemPrompt {
+    fn from(s: String) -> Self {
+        SystemPrompt::Text(s)
+    }
+}
+
+impl From<&String> for SystemPrompt {
+    fn from(s: &String) -> Self {
+        SystemPrompt::Text(s.clone())
+    }
+}
+
+impl From<Text> for SystemPrompt {
+    fn from(text: Text) -> Self {
+        SystemPrompt::Blocks(vec![SystemBlock::Text(text)])
+    }
+}
+
+impl From<Vec<Text>> for SystemPrompt {
+    fn from(texts: Vec<Text>) -> Self {
+        SystemPrompt::Blocks(texts.into_iter().map(SystemBlock::Text).collect())
+    }
+}
+
+/// Configures extended thinking
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingConfig {
+    /// Must be at least 1024 and less than `max_tokens`
+    Enabled {
+        budget_tokens: u32,
+    },
+    Disabled,
+}
+
+impl ThinkingConfig {
+    pub fn enabled(budget_tokens: u32) -> Self {
+        ThinkingConfig::Enabled { budget_tokens }
+    }
+}
+
+/// Request for `/v1/messages/count_tokens`
+///
+/// Accepts the same prompt as a `CreateMessagesRequest`; generation parameters are dropped when
+/// converting.
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(setter(into, strip_option))]
+pub struct CountTokensRequest {
+    pub messages: Vec<Message>,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub system: Option<SystemPrompt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub tools: Option<Vec<ToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub thinking: Option<ThinkingConfig>,
+}
+
+impl From<CreateMessagesRequest> for CountTokensRequest {
+    fn from(request: CreateMessagesRequest) -> Self {
+        CountTokensRequest {
+            messages: request.messages,
+            model: request.model,
+            system: request.system,
+            tools: request.tools,
+            tool_choice: request.tool_choice,
+            thinking: request.thinking,
+        }
+    }
+}
+
+impl From<&CreateMessagesRequest> for CountTokensRequest {
+    fn from(request: &CreateMessagesRequest) -> Self {
+        request.clone().into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CountTokensResponse {
+    pub input_tokens: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Builder)]
@@ -128,14 +366,82 @@ pub struct CreateMessagesResponse {
     #[serde(default)]
     pub model: Option<String>,
     #[serde(default)]
-    pub stop_reason: Option<String>,
+    pub stop_reason: Option<StopReason>,
     #[serde(default)]
     pub stop_sequence: Option<String>,
     #[serde(default)]
     pub usage: Option<Usage>,
 }
 
+/// Why the model stopped generating
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model reached a natural stopping point
+    EndTurn,
+    /// The response was cut off at `max_tokens`
+    MaxTokens,
+    /// One of the custom stop sequences was generated, see `stop_sequence`
+    StopSequence,
+    /// The model wants to use one or more tools
+    ToolUse,
+    /// A long running turn was paused, send the response back as is to continue
+    PauseTurn,
+    /// The model declined to respond
+    Refusal,
+    /// A stop reason this crate does not know yet
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+impl StopReason {
+    /// The response contains tool uses that need results before the conversation can continue
+    pub fn needs_tool_execution(&self) -> bool {
+        *self == StopReason::ToolUse
+    }
+
+    /// The response was cut off before the model finished
+    pub fn was_truncated(&self) -> bool {
+        *self == StopReason::MaxTokens
+    }
+
+    /// The turn is incomplete and can be resumed by sending the response back
+    pub fn is_paused(&self) -> bool {
+        *self == StopReason::PauseTurn
+    }
+
+    pub fn is_refusal(&self) -> bool {
+        *self == StopReason::Refusal
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            StopReason::EndTurn => "end_turn",
+            StopReason::MaxTokens => "max_tokens",
+            StopReason::StopSequence => "stop_sequence",
+            StopReason::ToolUse => "tool_use",
+            StopReason::PauseTurn => "pause_turn",
+            StopReason::Refusal => "refusal",
+            StopReason::Unknown(reason) => reason,
+        }
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl CreateMessagesResponse {
+    /// Returns all content as a single assistant message
+    pub fn message(&self) -> Message {
+        Message {
+            role: MessageRole::Assistant,
+            content: MessageContentList(self.content.clone().unwrap_or_default()),
+        }
+    }
+
     /// Returns the content as Messages so they are more easily reusable
     pub fn messages(&self) -> Vec<Message> {
         let Some(content) = &self.content else {
@@ -151,13 +457,59 @@ impl CreateMessagesResponse {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageContent {
     ToolUse(ToolUse),
     ToolResult(ToolResult),
     Text(Text),
-    // TODO: Implement images and documents
+    Image(Image),
+    Document(Document),
+    Thinking(Thinking),
+    RedactedThinking(RedactedThinking),
+    /// A content block of a type this crate does not support yet, kept as is
+    ///
+    /// Blocks of a known type that fail to deserialize are an error instead.
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+// Deserializes the known types, so only blocks of an unknown type fall back to `Unknown`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum KnownMessageContent {
+    ToolUse(ToolUse),
+    ToolResult(ToolResult),
+    Text(Text),
+    Image(Image),
+    Document(Document),
+    Thinking(Thinking),
+    RedactedThinking(RedactedThinking),
+    #[serde(other)]
+    Unknown,
+}
+
+impl<'de> Deserialize<'de> for MessageContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        Ok(
+            match KnownMessageContent::deserialize(&value).map_err(de::Error::custom)? {
+                KnownMessageContent::ToolUse(tool_use) => MessageContent::ToolUse(tool_use),
+                KnownMessageContent::ToolResult(tool_result) => {
+                    MessageContent::ToolResult(tool_result)
+                }
+                KnownMessageContent::Text(text) => MessageContent::Text(text),
+                KnownMessageContent::Image(image) => MessageContent::Image(image),
+                KnownMessageContent::Document(document) => MessageContent::Document(document),
+                KnownMessageContent::Thinking(thinking) => MessageContent::Thinking(thinking),
+                KnownMessageContent::RedactedThinking(redacted) => {
+                    MessageContent::RedactedThinking(redacted)
+                }
+                KnownMessageContent::Unknown => MessageContent::Unknown(value),
+            },
+        )
+    }
 }
 
 impl MessageContent {
@@ -184,6 +536,38 @@ impl MessageContent {
             None
         }
     }
+
+    pub fn as_image(&self) -> Option<&Image> {
+        if let MessageContent::Image(image) = self {
+            Some(image)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_document(&self) -> Option<&Document> {
+        if let MessageContent::Document(document) = self {
+            Some(document)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_thinking(&self) -> Option<&Thinking> {
+        if let MessageContent::Thinking(thinking) = self {
+            Some(thinking)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_redacted_thinking(&self) -> Option<&RedactedThinking> {
+        if let MessageContent::RedactedThinking(redacted_thinking) = self {
+            Some(redacted_thinking)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
@@ -194,6 +578,13 @@ pub struct ToolUse {
     pub name: String,
 }
 
+impl ToolUse {
+    /// Deserializes the tool input into a typed value
+    pub fn parse_input<T: DeserializeOwned>(&self) -> Result<T, AnthropicError> {
+        serde_json::from_value(self.input.clone()).map_err(AnthropicError::DeserializationError)
+    }
+}
+
 impl From<ToolUse> for MessageContent {
     fn from(tool_use: ToolUse) -> Self {
         MessageContent::ToolUse(tool_use)
@@ -212,6 +603,8 @@ pub struct ToolResult {
     pub tool_use_id: String,
     pub content: Option<String>,
     pub is_error: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 impl From<ToolResult> for MessageContent {
@@ -230,12 +623,15 @@ impl From<ToolResult> for MessageContentList {
 #[builder(setter(into, strip_option), default)]
 pub struct Text {
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 impl<S: AsRef<str>> From<S> for Text {
     fn from(s: S) -> Self {
         Text {
             text: s.as_ref().to_string(),
+            cache_control: None,
         }
     }
 }
@@ -252,112 +648,812 @@ impl From<Text> for MessageContentList {
     }
 }
 
-impl<S: AsRef<str>> From<S> for MessageContent {
-    fn from(s: S) -> Self {
-        MessageContent::Text(Text {
-            text: s.as_ref().to_string(),
-        })
-    }
+/// Extended thinking output
+///
+/// When continuing a conversation with tool use, thinking blocks must be passed back unmodified,
+/// including their signature.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+#[builder(setter(into, strip_option), default)]
+pub struct Thinking {
+    pub thinking: String,
+    /// Streamed thinking blocks start without a signature, it arrives in a `signature_delta`
+    #[serde(default)]
+    pub signature: String,
 }
 
-impl<S: AsRef<str>> From<S> for Message {
-    fn from(s: S) -> Self {
-        MessageBuilder::default()
-            .role(MessageRole::User)
-            .content(s.as_ref().to_string())
-            .build()
-            .expect("infallible")
+impl From<Thinking> for MessageContent {
+    fn from(thinking: Thinking) -> Self {
+        MessageContent::Thinking(thinking)
     }
 }
 
-// Any single AsRef<str> can be converted to a MessageContent, in a list as a single item
-impl<S: AsRef<str>> From<S> for MessageContentList {
-    fn from(s: S) -> Self {
-        MessageContentList(vec![s.as_ref().into()])
-    }
+/// Thinking flagged by safety systems, returned encrypted
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, Builder)]
+#[builder(setter(into, strip_option), default)]
+pub struct RedactedThinking {
+    pub data: String,
 }
 
-impl From<MessageContent> for MessageContentList {
-    fn from(content: MessageContent) -> Self {
-        MessageContentList(vec![content])
+impl From<RedactedThinking> for MessageContent {
+    fn from(redacted_thinking: RedactedThinking) -> Self {
+        MessageContent::RedactedThinking(redacted_thinking)
     }
 }
 
-impl Serialize for ToolChoice {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match self {
-            ToolChoice::Auto => {
-                serde::Serialize::serialize(&serde_json::json!({"type": "auto"}), serializer)
-            }
-            ToolChoice::Any => {
-                serde::Serialize::serialize(&serde_json::json!({"type": "any"}), serializer)
-            }
-            ToolChoice::Tool(name) => serde::Serialize::serialize(
-                &serde_json::json!({"type": "tool", "name": name}),
-                serializer,
-            ),
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Image {
+    pub source: ImageSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+impl Image {
+    /// Creates a base64 encoded image from raw bytes
+    pub fn from_bytes(media_type: ImageMediaType, bytes: impl AsRef<[u8]>) -> Self {
+        Image {
+            source: ImageSource::Base64 {
+                media_type,
+                data: BASE64_STANDARD.encode(bytes),
+            },
+            cache_control: None,
         }
     }
-}
-#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
-#[serde(rename_all = "snake_case", tag = "type")]
-pub enum ContentBlockDelta {
-    TextDelta { text: String },
-    InputJsonDelta { partial_json: String },
-}
 
-#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct MessageDelta {
-    pub stop_reason: Option<String>,
-    pub stop_sequence: Option<String>,
+    /// Reads an image from disk, inferring the media type from the file extension
+    pub async fn from_path(path: impl AsRef<Path>) -> Result<Self, AnthropicError> {
+        let path = path.as_ref();
+        let media_type = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImageMediaType::from_extension)
+            .ok_or_else(|| {
+                AnthropicError::BadRequest(format!("unsupported image type: {}", path.display()))
+            })?;
+        let bytes = tokio::fs::read(path).await?;
+
+        Ok(Self::from_bytes(media_type, bytes))
+    }
+
+    /// References an image by url
+    pub fn from_url(url: impl Into<String>) -> Self {
+        Image {
+            source: ImageSource::Url { url: url.into() },
+            cache_control: None,
+        }
+    }
+
+    /// References an image uploaded with the files api
+    pub fn from_file_id(file_id: impl Into<String>) -> Self {
+        Image {
+            source: ImageSource::File {
+                file_id: file_id.into(),
+            },
+            cache_control: None,
+        }
+    }
+
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all = "snake_case", tag = "type")]
-pub enum MessagesStreamEvent {
-    MessageStart {
-        message: MessageStart,
-        usage: Option<Usage>,
-    },
-    ContentBlockStart {
-        index: usize,
-        content_block: MessageContent,
-    },
-    ContentBlockDelta {
-        index: usize,
-        delta: ContentBlockDelta,
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 {
+        media_type: ImageMediaType,
+        data: String,
     },
-    ContentBlockStop {
-        index: usize,
+    Url {
+        url: String,
     },
-    MessageDelta {
-        delta: MessageDelta,
-        #[serde(default)]
-        usage: Option<Usage>,
+    /// A file uploaded with the files api, requires the files api beta
+    File {
+        file_id: String,
     },
-    MessageStop,
-}
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
-pub struct MessageStart {
-    pub id: String,
-    pub model: String,
-    pub role: String,
-    pub content: Vec<MessageContent>,
-    #[serde(default)]
-    pub stop_reason: Option<String>,
-    #[serde(default)]
-    pub stop_sequence: Option<String>,
-    #[serde(default)]
-    pub usage: Option<Usage>,
 }
 
-pub type CreateMessagesResponseStream =
-    Pin<Box<dyn Stream<Item = Result<MessagesStreamEvent, AnthropicError>> + Send>>;
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ImageMediaType {
+    #[serde(rename = "image/jpeg")]
+    Jpeg,
+    #[serde(rename = "image/png")]
+    Png,
+    #[serde(rename = "image/gif")]
+    Gif,
+    #[serde(rename = "image/webp")]
+    Webp,
+}
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+impl ImageMediaType {
+    /// Maps a file extension to a supported media type
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(ImageMediaType::Jpeg),
+            "png" => Some(ImageMediaType::Png),
+            "gif" => Some(ImageMediaType::Gif),
+            "webp" => Some(ImageMediaType::Webp),
+            _ => None,
+        }
+    }
+}
+
+impl From<Image> for MessageContent {
+    fn from(image: Image) -> Self {
+        MessageContent::Image(image)
+    }
+}
+
+impl From<Image> for MessageContentList {
+    fn from(image: Image) -> Self {
+        MessageContentList(vec![image.into()])
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Document {
+    pub source: DocumentSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<Citations>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+impl Document {
+    /// Creates a base64 encoded pdf document from raw bytes
+    pub fn from_pdf_bytes(bytes: impl AsRef<[u8]>) -> Self {
+        Self::from_source(DocumentSource::Base64 {
+            media_type: "application/pdf".to_string(),
+            data: BASE64_STANDARD.encode(bytes),
+        })
+    }
+
+    /// Reads a pdf document from disk
+    pub async fn from_pdf_path(path: impl AsRef<Path>) -> Result<Self, AnthropicError> {
+        let bytes = tokio::fs::read(path).await?;
+
+        Ok(Self::from_pdf_bytes(bytes))
+    }
+
+    /// References a pdf document by url
+    pub fn from_pdf_url(url: impl Into<String>) -> Self {
+        Self::from_source(DocumentSource::Url { url: url.into() })
+    }
+
+    /// Creates a plain text document
+    pub fn from_text(text: impl Into<String>) -> Self {
+        Self::from_source(DocumentSource::Text {
+            media_type: "text/plain".to_string(),
+            data: text.into(),
+        })
+    }
+
+    /// Creates a document from custom content blocks, i.e. for fine grained citations
+    pub fn from_content(content: Vec<MessageContent>) -> Self {
+        Self::from_source(DocumentSource::Content {
+            content: MessageContentList(content),
+        })
+    }
+
+    /// References a document uploaded with the files api
+    pub fn from_file_id(file_id: impl Into<String>) -> Self {
+        Self::from_source(DocumentSource::File {
+            file_id: file_id.into(),
+        })
+    }
+
+    pub fn from_source(source: DocumentSource) -> Self {
+        Document {
+            source,
+            title: None,
+            context: None,
+            citations: None,
+            cache_control: None,
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    pub fn with_citations(mut self, enabled: bool) -> Self {
+        self.citations = Some(Citations { enabled });
+        self
+    }
+
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DocumentSource {
+    Base64 {
+        media_type: String,
+        data: String,
+    },
+    Url {
+        url: String,
+    },
+    Text {
+        media_type: String,
+        data: String,
+    },
+    Content {
+        content: MessageContentList,
+    },
+    /// A file uploaded with the files api, requires the files api beta
+    File {
+        file_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct Citations {
+    pub enabled: bool,
+}
+
+impl From<Document> for MessageContent {
+    fn from(document: Document) -> Self {
+        MessageContent::Document(document)
+    }
+}
+
+impl From<Document> for MessageContentList {
+    fn from(document: Document) -> Self {
+        MessageContentList(vec![document.into()])
+    }
+}
+
+impl<S: AsRef<str>> From<S> for MessageContent {
+    fn from(s: S) -> Self {
+        MessageContent::Text(s.into())
+    }
+}
+
+impl<S: AsRef<str>> From<S> for Message {
+    fn from(s: S) -> Self {
+        MessageBuilder::default()
+            .role(MessageRole::User)
+            .content(s.as_ref().to_string())
+            .build()
+            .expect("infallible")
+    }
+}
+
+// Any single AsRef<str> can be converted to a MessageContent, in a list as a single item
+impl<S: AsRef<str>> From<S> for MessageContentList {
+    fn from(s: S) -> Self {
+        MessageContentList(vec![s.as_ref().into()])
+    }
+}
+
+impl From<MessageContent> for MessageContentList {
+    fn from(content: MessageContent) -> Self {
+        MessageContentList(vec![content])
+    }
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ToolChoice::Auto => {
+                serde::Serialize::serialize(&serde_json::json!({"type": "auto"}), serializer)
+            }
+            ToolChoice::Any => {
+                serde::Serialize::serialize(&serde_json::json!({"type": "any"}), serializer)
+            }
+            ToolChoice::Tool(name) => serde::Serialize::serialize(
+                &serde_json::json!({"type": "tool", "name": name}),
+                serializer,
+            ),
+        }
+    }
+}
+
+/// A tool that can be provided to the model
+///
+/// Custom tools are defined by a name and a json schema for their input. Server and built-in tools
+/// are defined by Anthropic and only need to be enabled.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ToolDefinition {
+    Custom(Tool),
+    Server(ServerTool),
+    /// Any other tool definition, i.e. a server tool version this crate does not know yet
+    Other(serde_json::Map<String, Value>),
+}
+
+impl From<Tool> for ToolDefinition {
+    fn from(tool: Tool) -> Self {
+        ToolDefinition::Custom(tool)
+    }
+}
+
+impl From<ServerTool> for ToolDefinition {
+    fn from(tool: ServerTool) -> Self {
+        ToolDefinition::Server(tool)
+    }
+}
+
+impl From<serde_json::Map<String, Value>> for ToolDefinition {
+    fn from(tool: serde_json::Map<String, Value>) -> Self {
+        ToolDefinition::Other(tool)
+    }
+}
+
+/// A custom tool, executed client side
+///
+/// # Example
+///
+/// ```
+/// # use async_anthropic::types::ToolBuilder;
+/// # use serde_json::json;
+/// let tool = ToolBuilder::default()
+///     .name("get_weather")
+///     .description("Get the current weather in a given location")
+///     .input_schema(json!({
+///         "type": "object",
+///         "properties": {"location": {"type": "string"}},
+///         "required": ["location"]
+///     }))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Builder)]
+#[builder(setter(into, strip_option))]
+pub struct Tool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub description: Option<String>,
+    pub input_schema: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub cache_control: Option<CacheControl>,
+}
+
+/// A custom tool with its input schema derived from a Rust type
+///
+/// The doc comment on `T` is used as the tool description unless one is set explicitly.
+///
+/// # Example
+///
+/// ```
+/// # use async_anthropic::types::{ToolUse, TypedTool};
+/// #[derive(schemars::JsonSchema, serde::Deserialize)]
+/// /// Get the current weather in a given location
+/// struct GetWeather {
+///     /// The city and state, e.g. San Francisco, CA
+///     location: String,
+/// }
+///
+/// let tool = TypedTool::<GetWeather>::new("get_weather");
+/// # let tool_use = ToolUse { name: "get_weather".into(), input: serde_json::json!({"location": "Amsterdam"}), ..Default::default() };
+/// let input = tool.parse(&tool_use).unwrap();
+/// # assert_eq!(input.location, "Amsterdam");
+/// ```
+#[cfg(feature = "schemars")]
+#[derive(Debug, Clone)]
+pub struct TypedTool<T> {
+    tool: Tool,
+    _marker: std::marker::PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "schemars")]
+impl<T: schemars::JsonSchema + DeserializeOwned> TypedTool<T> {
+    pub fn new(name: impl Into<String>) -> Self {
+        let mut input_schema = schemars::schema_for!(T).to_value();
+        let mut description = None;
+
+        if let Some(schema) = input_schema.as_object_mut() {
+            schema.remove("$schema");
+            schema.remove("title");
+            description = schema
+                .remove("description")
+                .and_then(|d| d.as_str().map(str::to_string));
+        }
+
+        TypedTool {
+            tool: Tool {
+                name: name.into(),
+                description,
+                input_schema,
+                cache_control: None,
+            },
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.tool.description = Some(description.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.tool.name
+    }
+
+    pub fn tool(&self) -> &Tool {
+        &self.tool
+    }
+
+    /// Parses the input of a tool use for this tool
+    pub fn parse(&self, tool_use: &ToolUse) -> Result<T, AnthropicError> {
+        if tool_use.name != self.tool.name {
+            return Err(AnthropicError::BadRequest(format!(
+                "expected tool use for {}, got {}",
+                self.tool.name, tool_use.name
+            )));
+        }
+        tool_use.parse_input()
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T> From<TypedTool<T>> for Tool {
+    fn from(typed: TypedTool<T>) -> Self {
+        typed.tool
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T> From<TypedTool<T>> for ToolDefinition {
+    fn from(typed: TypedTool<T>) -> Self {
+        ToolDefinition::Custom(typed.tool)
+    }
+}
+
+/// Tools provided by Anthropic, identified by their versioned type
+///
+/// Other tools and versions can be passed as a raw `ToolDefinition::Other`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum ServerTool {
+    #[serde(rename = "bash_20250124")]
+    Bash {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    #[serde(rename = "text_editor_20250124")]
+    TextEditor {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    #[serde(rename = "computer_20250124")]
+    Computer {
+        name: String,
+        display_width_px: u32,
+        display_height_px: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        display_number: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    #[serde(rename = "web_search_20250305")]
+    WebSearch {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_uses: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        allowed_domains: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        blocked_domains: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user_location: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    #[serde(rename = "code_execution_20250522")]
+    CodeExecution {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+}
+
+impl ServerTool {
+    pub fn bash() -> Self {
+        ServerTool::Bash {
+            name: "bash".to_string(),
+            cache_control: None,
+        }
+    }
+
+    pub fn text_editor() -> Self {
+        ServerTool::TextEditor {
+            name: "str_replace_editor".to_string(),
+            cache_control: None,
+        }
+    }
+
+    pub fn computer(display_width_px: u32, display_height_px: u32) -> Self {
+        ServerTool::Computer {
+            name: "computer".to_string(),
+            display_width_px,
+            display_height_px,
+            display_number: None,
+            cache_control: None,
+        }
+    }
+
+    pub fn web_search() -> Self {
+        ServerTool::WebSearch {
+            name: "web_search".to_string(),
+            max_uses: None,
+            allowed_domains: None,
+            blocked_domains: None,
+            user_location: None,
+            cache_control: None,
+        }
+    }
+
+    pub fn code_execution() -> Self {
+        ServerTool::CodeExecution {
+            name: "code_execution".to_string(),
+            cache_control: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ServerTool::Bash { name, .. }
+            | ServerTool::TextEditor { name, .. }
+            | ServerTool::Computer { name, .. }
+            | ServerTool::WebSearch { name, .. }
+            | ServerTool::CodeExecution { name, .. } => name,
+        }
+    }
+
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        match &mut self {
+            ServerTool::Bash {
+                cache_control: field,
+                ..
+            }
+            | ServerTool::TextEditor {
+                cache_control: field,
+                ..
+            }
+            | ServerTool::Computer {
+                cache_control: field,
+                ..
+            }
+            | ServerTool::WebSearch {
+                cache_control: field,
+                ..
+            }
+            | ServerTool::CodeExecution {
+                cache_control: field,
+                ..
+            } => *field = Some(cache_control),
+        }
+        self
+    }
+}
+
+/// Marks a cache breakpoint for prompt caching
+///
+/// Everything in the prompt up to and including the block with the breakpoint is cached. The order
+/// is tools, system and then messages.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CacheControl {
+    Ephemeral {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<CacheTtl>,
+    },
+}
+
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        CacheControl::Ephemeral { ttl: None }
+    }
+
+    pub fn ephemeral_with_ttl(ttl: CacheTtl) -> Self {
+        CacheControl::Ephemeral { ttl: Some(ttl) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CacheTtl {
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+#[derive(Clone, Serialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ContentBlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    /// A delta of a type this crate does not support yet
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum KnownContentBlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+impl<'de> Deserialize<'de> for ContentBlockDelta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        Ok(
+            match KnownContentBlockDelta::deserialize(&value).map_err(de::Error::custom)? {
+                KnownContentBlockDelta::TextDelta { text } => ContentBlockDelta::TextDelta { text },
+                KnownContentBlockDelta::InputJsonDelta { partial_json } => {
+                    ContentBlockDelta::InputJsonDelta { partial_json }
+                }
+                KnownContentBlockDelta::ThinkingDelta { thinking } => {
+                    ContentBlockDelta::ThinkingDelta { thinking }
+                }
+                KnownContentBlockDelta::SignatureDelta { signature } => {
+                    ContentBlockDelta::SignatureDelta { signature }
+                }
+                KnownContentBlockDelta::Unknown => ContentBlockDelta::Unknown(value),
+            },
+        )
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct MessageDelta {
+    pub stop_reason: Option<StopReason>,
+    pub stop_sequence: Option<String>,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum MessagesStreamEvent {
+    MessageStart {
+        message: MessageStart,
+        usage: Option<Usage>,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: MessageContent,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: ContentBlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    /// An event of a type this crate does not support yet, safe to skip
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum KnownMessagesStreamEvent {
+    MessageStart {
+        message: MessageStart,
+        usage: Option<Usage>,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: MessageContent,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: ContentBlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    #[serde(other)]
+    Unknown,
+}
+
+impl<'de> Deserialize<'de> for MessagesStreamEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        Ok(
+            match KnownMessagesStreamEvent::deserialize(&value).map_err(de::Error::custom)? {
+                KnownMessagesStreamEvent::MessageStart { message, usage } => {
+                    MessagesStreamEvent::MessageStart { message, usage }
+                }
+                KnownMessagesStreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                } => MessagesStreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                },
+                KnownMessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                    MessagesStreamEvent::ContentBlockDelta { index, delta }
+                }
+                KnownMessagesStreamEvent::ContentBlockStop { index } => {
+                    MessagesStreamEvent::ContentBlockStop { index }
+                }
+                KnownMessagesStreamEvent::MessageDelta { delta, usage } => {
+                    MessagesStreamEvent::MessageDelta { delta, usage }
+                }
+                KnownMessagesStreamEvent::MessageStop => MessagesStreamEvent::MessageStop,
+                KnownMessagesStreamEvent::Unknown => MessagesStreamEvent::Unknown(value),
+            },
+        )
+    }
+}
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MessageStart {
+    pub id: String,
+    pub model: String,
+    pub role: String,
+    pub content: Vec<MessageContent>,
+    #[serde(default)]
+    pub stop_reason: Option<StopReason>,
+    #[serde(default)]
+    pub stop_sequence: Option<String>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+pub type CreateMessagesResponseStream =
+    Pin<Box<dyn Stream<Item = Result<MessagesStreamEvent, AnthropicError>> + Send>>;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ListModelsResponse {
     #[serde(default)]
     pub data: Vec<Model>,
@@ -380,6 +1476,185 @@ pub struct Model {
 
 pub type GetModelResponse = Model;
 
+/// Request for `/v1/messages/batches`
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(setter(into))]
+pub struct CreateMessageBatchRequest {
+    pub requests: Vec<BatchRequest>,
+}
+
+impl From<Vec<BatchRequest>> for CreateMessageBatchRequest {
+    fn from(requests: Vec<BatchRequest>) -> Self {
+        CreateMessageBatchRequest { requests }
+    }
+}
+
+/// A single request in a message batch
+///
+/// The `custom_id` is used to match results to requests, as results are not returned in order.
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(setter(into))]
+pub struct BatchRequest {
+    pub custom_id: String,
+    pub params: CreateMessagesRequest,
+}
+
+impl BatchRequest {
+    pub fn new(custom_id: impl Into<String>, params: impl Into<CreateMessagesRequest>) -> Self {
+        BatchRequest {
+            custom_id: custom_id.into(),
+            params: params.into(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MessageBatch {
+    pub id: String,
+    pub processing_status: BatchProcessingStatus,
+    pub request_counts: BatchRequestCounts,
+    pub created_at: String,
+    pub expires_at: String,
+    #[serde(default)]
+    pub ended_at: Option<String>,
+    #[serde(default)]
+    pub archived_at: Option<String>,
+    #[serde(default)]
+    pub cancel_initiated_at: Option<String>,
+    /// Url of the results file, available once processing has ended
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchProcessingStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct BatchRequestCounts {
+    #[serde(default)]
+    pub processing: u32,
+    #[serde(default)]
+    pub succeeded: u32,
+    #[serde(default)]
+    pub errored: u32,
+    #[serde(default)]
+    pub canceled: u32,
+    #[serde(default)]
+    pub expired: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ListMessageBatchesResponse {
+    #[serde(default)]
+    pub data: Vec<MessageBatch>,
+
+    #[serde(default)]
+    pub first_id: Option<String>,
+    pub has_more: bool,
+    #[serde(default)]
+    pub last_id: Option<String>,
+}
+
+/// A single line of the results of a message batch
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: BatchResultKind,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchResultKind {
+    Succeeded { message: CreateMessagesResponse },
+    Errored { error: ErrorResponse },
+    Canceled,
+    Expired,
+}
+
+impl BatchResult {
+    /// Returns the response if the request succeeded
+    pub fn message(&self) -> Option<&CreateMessagesResponse> {
+        match &self.result {
+            BatchResultKind::Succeeded { message } => Some(message),
+            _ => None,
+        }
+    }
+}
+
+pub type BatchResultStream =
+    Pin<Box<dyn Stream<Item = Result<BatchResult, AnthropicError>> + Send>>;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeleteMessageBatchResponse {
+    pub id: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FileMetadata {
+    pub id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+    /// Only files created by tools can be downloaded
+    #[serde(default)]
+    pub downloadable: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ListFilesResponse {
+    #[serde(default)]
+    pub data: Vec<FileMetadata>,
+
+    #[serde(default)]
+    pub first_id: Option<String>,
+    pub has_more: bool,
+    #[serde(default)]
+    pub last_id: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeleteFileResponse {
+    pub id: String,
+}
+
+/// Cursor parameters for list endpoints
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default, Builder)]
+#[builder(setter(into, strip_option), default)]
+pub struct ListParams {
+    /// Returns the page of results immediately before this id
+    pub before_id: Option<String>,
+    /// Returns the page of results immediately after this id
+    pub after_id: Option<String>,
+    /// Number of items per page
+    pub limit: Option<u32>,
+}
+
+impl ListParams {
+    /// Appends the parameters as a query string to the path
+    pub(crate) fn to_path(&self, path: &str) -> String {
+        let params = [
+            ("before_id", self.before_id.clone()),
+            ("after_id", self.after_id.clone()),
+            ("limit", self.limit.map(|limit| limit.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| format!("{key}={value}")))
+        .collect::<Vec<_>>();
+
+        if params.is_empty() {
+            path.to_string()
+        } else {
+            format!("{path}?{}", params.join("&"))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -406,6 +1681,8 @@ mod tests {
 
         assert_eq!(usage.input_tokens, Some(10));
         assert_eq!(usage.output_tokens, Some(12));
+        assert_eq!(usage.cache_creation_input_tokens, Some(0));
+        assert_eq!(usage.cache_read_input_tokens, Some(0));
         assert_eq!(
             response.id,
             Some("msg_01KkaCASJuaAgTWD2wqdbwC8".to_string())
@@ -414,7 +1691,7 @@ mod tests {
             response.model,
             Some("claude-3-5-sonnet-20241022".to_string())
         );
-        assert_eq!(response.stop_reason, Some("end_turn".to_string()));
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
         assert_eq!(response.stop_sequence, None);
         assert_eq!(
             response
@@ -425,9 +1702,7 @@ mod tests {
                 .first()
                 .unwrap()
                 .as_text(),
-            Some(&Text {
-                text: "Hi! How can I help you today?".to_string(),
-            })
+            Some(&Text::from("Hi! How can I help you today?"))
         );
     }
 
@@ -439,12 +1714,330 @@ mod tests {
             message,
             Message {
                 role: MessageRole::User,
-                content: MessageContentList(vec![MessageContent::Text(Text {
-                    text: "Hello world!".to_string()
-                })]),
+                content: MessageContentList(vec![MessageContent::Text(Text::from("Hello world!"))]),
             }
         );
 
         assert_eq!(message.text(), Some("Hello world!".to_string()));
     }
+
+    #[test]
+    fn test_usage_merge_and_add() {
+        let mut streamed: Usage = serde_json::from_value(json!({
+            "input_tokens": 10,
+            "output_tokens": 1,
+            "cache_read_input_tokens": 100,
+            "cache_creation": {"ephemeral_5m_input_tokens": 5, "ephemeral_1h_input_tokens": 0},
+            "service_tier": "standard"
+        }))
+        .unwrap();
+        streamed.merge(&serde_json::from_value(json!({"output_tokens": 42})).unwrap());
+
+        assert_eq!(streamed.input_tokens, Some(10));
+        assert_eq!(streamed.output_tokens, Some(42));
+        assert_eq!(streamed.cache_read_input_tokens, Some(100));
+        assert_eq!(streamed.total_input_tokens(), 110);
+
+        let total = streamed.clone()
+            + Usage {
+                input_tokens: Some(5),
+                output_tokens: Some(8),
+                server_tool_use: Some(ServerToolUsage {
+                    web_search_requests: 2,
+                }),
+                ..Default::default()
+            };
+
+        assert_eq!(total.input_tokens, Some(15));
+        assert_eq!(total.output_tokens, Some(50));
+        assert_eq!(total.cache_read_input_tokens, Some(100));
+        assert_eq!(total.cache_creation.unwrap().ephemeral_5m_input_tokens, 5);
+        assert_eq!(total.server_tool_use.unwrap().web_search_requests, 2);
+        assert_eq!(total.service_tier.as_deref(), Some("standard"));
+    }
+
+    #[test]
+    fn test_serialize_image() {
+        let content: MessageContent = Image::from_bytes(ImageMediaType::Png, b"hello").into();
+
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            json!({
+                "type": "image",
+                "source": {
+                    "type": "base64",
+                    "media_type": "image/png",
+                    "data": "aGVsbG8="
+                }
+            })
+        );
+
+        let content: MessageContent = Image::from_url("https://example.com/cat.jpg").into();
+
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            json!({
+                "type": "image",
+                "source": {"type": "url", "url": "https://example.com/cat.jpg"}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image_from_path() {
+        let path = std::env::temp_dir().join(format!("async-anthropic-{}.png", std::process::id()));
+        tokio::fs::write(&path, b"hello").await.unwrap();
+
+        let image = Image::from_path(&path).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(
+            image.unwrap(),
+            Image::from_bytes(ImageMediaType::Png, b"hello")
+        );
+
+        let error = Image::from_path("cat.bmp").await.unwrap_err();
+        assert!(matches!(error, AnthropicError::BadRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_document_from_pdf_path() {
+        let path = std::env::temp_dir().join(format!("async-anthropic-{}.pdf", std::process::id()));
+        tokio::fs::write(&path, b"%PDF").await.unwrap();
+
+        let document = Document::from_pdf_path(&path).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(document.unwrap(), Document::from_pdf_bytes(b"%PDF"));
+    }
+
+    #[test]
+    fn test_serialize_tools() {
+        let tools: Vec<ToolDefinition> = vec![
+            ToolBuilder::default()
+                .name("get_weather")
+                .input_schema(json!({"type": "object"}))
+                .build()
+                .unwrap()
+                .into(),
+            ServerTool::web_search().into(),
+            ServerTool::bash()
+                .with_cache_control(CacheControl::ephemeral())
+                .into(),
+            json!({"type": "web_fetch_20250910", "name": "web_fetch", "max_uses": 5})
+                .as_object()
+                .unwrap()
+                .clone()
+                .into(),
+        ];
+
+        let value = serde_json::to_value(&tools).unwrap();
+
+        assert_eq!(
+            value,
+            json!([
+                {"name": "get_weather", "input_schema": {"type": "object"}},
+                {"type": "web_search_20250305", "name": "web_search"},
+                {"type": "bash_20250124", "name": "bash", "cache_control": {"type": "ephemeral"}},
+                {"type": "web_fetch_20250910", "name": "web_fetch", "max_uses": 5}
+            ])
+        );
+        assert_eq!(
+            serde_json::from_value::<Vec<ToolDefinition>>(value).unwrap(),
+            tools
+        );
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_typed_tool() {
+        /// Search the knowledge base
+        #[derive(schemars::JsonSchema, Deserialize, Debug, PartialEq)]
+        struct Search {
+            query: String,
+            limit: Option<u32>,
+        }
+
+        let tool = TypedTool::<Search>::new("search");
+
+        assert_eq!(
+            tool.tool().description.as_deref(),
+            Some("Search the knowledge base")
+        );
+        assert_eq!(tool.tool().input_schema["type"], "object");
+        assert_eq!(tool.tool().input_schema["required"], json!(["query"]));
+
+        let tool_use = ToolUse {
+            id: "toolu_1".to_string(),
+            name: "search".to_string(),
+            input: json!({"query": "rust"}),
+        };
+
+        assert_eq!(
+            tool.parse(&tool_use).unwrap(),
+            Search {
+                query: "rust".to_string(),
+                limit: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_serialize_cache_control() {
+        let request = CreateMessagesRequestBuilder::default()
+            .model("claude")
+            .messages(vec!["Hello".into()])
+            .system(vec![TextBuilder::default()
+                .text("A very long system prompt")
+                .cache_control(CacheControl::ephemeral_with_ttl(CacheTtl::OneHour))
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let value = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(
+            value["system"],
+            json!([{
+                "type": "text",
+                "text": "A very long system prompt",
+                "cache_control": {"type": "ephemeral", "ttl": "1h"}
+            }])
+        );
+        assert_eq!(
+            value["messages"][0]["content"],
+            json!([{"type": "text", "text": "Hello"}])
+        );
+
+        let request = CreateMessagesRequestBuilder::default()
+            .model("claude")
+            .messages(vec!["Hello".into()])
+            .system("Be brief")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["system"],
+            "Be brief"
+        );
+
+        let content: MessageContent = Document::from_pdf_url("https://example.com/a.pdf")
+            .with_cache_control(CacheControl::ephemeral())
+            .into();
+
+        assert_eq!(
+            serde_json::to_value(&content).unwrap()["cache_control"],
+            json!({"type": "ephemeral"})
+        );
+    }
+
+    #[test]
+    fn test_serialize_document() {
+        let content: MessageContent = Document::from_text("The grass is green.")
+            .with_title("Facts")
+            .with_citations(true)
+            .into();
+
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            json!({
+                "type": "document",
+                "source": {
+                    "type": "text",
+                    "media_type": "text/plain",
+                    "data": "The grass is green."
+                },
+                "title": "Facts",
+                "citations": {"enabled": true}
+            })
+        );
+
+        let content: MessageContent = Document::from_content(vec![
+            MessageContent::from("First chunk"),
+            MessageContent::from("Second chunk"),
+        ])
+        .into();
+
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            json!({
+                "type": "document",
+                "source": {
+                    "type": "content",
+                    "content": [
+                        {"type": "text", "text": "First chunk"},
+                        {"type": "text", "text": "Second chunk"}
+                    ]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_content_and_events() {
+        let block = json!({"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search"});
+        let content: MessageContent = serde_json::from_value(block.clone()).unwrap();
+
+        assert_eq!(content, MessageContent::Unknown(block.clone()));
+        assert_eq!(serde_json::to_value(&content).unwrap(), block);
+
+        let text: MessageContent =
+            serde_json::from_value(json!({"type": "text", "text": "Hello"})).unwrap();
+        assert_eq!(text.as_text().unwrap().text, "Hello");
+
+        let event = json!({"type": "content_block_delta", "index": 0, "delta": {"type": "citations_delta", "citation": {}}});
+        let event: MessagesStreamEvent = serde_json::from_value(event).unwrap();
+        assert!(matches!(
+            event,
+            MessagesStreamEvent::ContentBlockDelta {
+                delta: ContentBlockDelta::Unknown(_),
+                ..
+            }
+        ));
+
+        let event: MessagesStreamEvent =
+            serde_json::from_value(json!({"type": "message_annotation", "data": 1})).unwrap();
+        assert!(matches!(event, MessagesStreamEvent::Unknown(_)));
+    }
+
+    #[test]
+    fn test_malformed_known_types_are_errors() {
+        let tool_use = json!({"type": "tool_use", "id": "toolu_1"});
+        assert!(serde_json::from_value::<MessageContent>(tool_use).is_err());
+
+        let thinking: MessageContent =
+            serde_json::from_value(json!({"type": "thinking", "thinking": ""})).unwrap();
+        assert!(thinking.as_thinking().is_some());
+
+        let delta = json!({"type": "text_delta", "partial_json": "{"});
+        assert!(serde_json::from_value::<ContentBlockDelta>(delta).is_err());
+
+        let event =
+            json!({"type": "content_block_start", "content_block": {"type": "text", "text": ""}});
+        assert!(serde_json::from_value::<MessagesStreamEvent>(event).is_err());
+
+        let event =
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "image"}});
+        assert!(serde_json::from_value::<MessagesStreamEvent>(event).is_err());
+    }
+
+    #[test]
+    fn test_stop_reason() {
+        let reasons: Vec<StopReason> = serde_json::from_value(json!([
+            "tool_use",
+            "max_tokens",
+            "model_context_window_exceeded"
+        ]))
+        .unwrap();
+
+        assert!(reasons[0].needs_tool_execution());
+        assert!(reasons[1].was_truncated());
+        assert_eq!(
+            reasons[2],
+            StopReason::Unknown("model_context_window_exceeded".to_string())
+        );
+        assert_eq!(
+            serde_json::to_value(&reasons).unwrap(),
+            json!(["tool_use", "max_tokens", "model_context_window_exceeded"])
+        );
+    }
 }
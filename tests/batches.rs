@@ -0,0 +1,442 @@
+use async_anthropic::{
+    batches::BatchJob,
+    errors::{AnthropicError, ApiErrorKind},
+    types::{BatchProcessingStatus, BatchRequest, BatchResultKind, CreateMessagesRequestBuilder},
+    Client,
+};
+use async_trait::async_trait;
+use backoff::ExponentialBackoffBuilder;
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio_stream::StreamExt as _;
+use wiremock::{
+    matchers::{body_partial_json, method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[async_trait]
+pub trait MockApp {
+    async fn setup() -> MockServer;
+}
+
+struct TestSetup;
+
+#[async_trait]
+impl MockApp for TestSetup {
+    async fn setup() -> MockServer {
+        MockServer::start().await
+    }
+}
+
+#[tokio::test]
+async fn test_create_batch() {
+    let server = TestSetup::setup().await;
+
+    let batch = json!({
+        "id": "msgbatch_1",
+        "type": "message_batch",
+        "processing_status": "in_progress",
+        "request_counts": {"processing": 1, "succeeded": 0, "errored": 0, "canceled": 0, "expired": 0},
+        "created_at": "2024-09-24T18:37:24.100435Z",
+        "expires_at": "2024-09-25T18:37:24.100435Z",
+        "ended_at": null,
+        "archived_at": null,
+        "cancel_initiated_at": null,
+        "results_url": null
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches"))
+        .and(body_partial_json(json!({
+            "requests": [{"custom_id": "first", "params": {"model": "test-model"}}]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let params = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let batch = client
+        .batches()
+        .create(vec![BatchRequest::new("first", params)])
+        .await
+        .unwrap();
+
+    assert_eq!(batch.id, "msgbatch_1");
+    assert_eq!(batch.processing_status, BatchProcessingStatus::InProgress);
+    assert_eq!(batch.request_counts.processing, 1);
+}
+
+#[tokio::test]
+async fn test_get_list_and_cancel_batch() {
+    let server = TestSetup::setup().await;
+
+    let batch = |status: &str| {
+        json!({
+            "id": "msgbatch_1",
+            "type": "message_batch",
+            "processing_status": status,
+            "request_counts": {"processing": 1, "succeeded": 0, "errored": 0, "canceled": 0, "expired": 0},
+            "created_at": "2024-09-24T18:37:24.100435Z",
+            "expires_at": "2024-09-25T18:37:24.100435Z",
+            "ended_at": null,
+            "archived_at": null,
+            "cancel_initiated_at": null,
+            "results_url": null
+        })
+    };
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches/msgbatch_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch("in_progress")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [batch("ended")],
+            "has_more": false,
+            "first_id": "msgbatch_1",
+            "last_id": "msgbatch_1"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches/msgbatch_1/cancel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch("canceling")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let batch = client.batches().get("msgbatch_1").await.unwrap();
+    assert_eq!(batch.processing_status, BatchProcessingStatus::InProgress);
+
+    let list = client.batches().list().await.unwrap();
+    assert_eq!(list.data[0].processing_status, BatchProcessingStatus::Ended);
+
+    let batch = client.batches().cancel("msgbatch_1").await.unwrap();
+    assert_eq!(batch.processing_status, BatchProcessingStatus::Canceling);
+}
+
+#[tokio::test]
+async fn test_delete_batch() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("DELETE"))
+        .and(path("/v1/messages/batches/msgbatch_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "msgbatch_1",
+            "type": "message_batch_deleted"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("DELETE"))
+        .and(path("/v1/messages/batches/msgbatch_2"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Batch is still processing"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let deleted = client.batches().delete("msgbatch_1").await.unwrap();
+    assert_eq!(deleted.id, "msgbatch_1");
+
+    let result = client.batches().delete("msgbatch_2").await;
+    assert!(
+        result.as_ref().unwrap_err().kind() == Some(ApiErrorKind::InvalidRequest),
+        "actual: {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_stream_batch_results() {
+    let server = TestSetup::setup().await;
+
+    let body = [
+        json!({"custom_id": "first", "result": {"type": "succeeded", "message": {
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "test-model",
+            "content": [{"type": "text", "text": "Hello!"}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 10, "output_tokens": 2}
+        }}}),
+        json!({"custom_id": "second", "result": {"type": "errored", "error": {
+            "type": "error",
+            "error": {"type": "invalid_request_error", "message": "max_tokens: Field required"}
+        }}}),
+        json!({"custom_id": "third", "result": {"type": "expired"}}),
+    ]
+    .map(|line| line.to_string())
+    .join("\n");
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches/msgbatch_1/results"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let results = client
+        .batches()
+        .results("msgbatch_1")
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].custom_id, "first");
+    assert_eq!(
+        results[0].message().unwrap().message().text(),
+        Some("Hello!".to_string())
+    );
+    let BatchResultKind::Errored { error } = &results[1].result else {
+        panic!("unexpected result: {:?}", results[1].result);
+    };
+    assert_eq!(error.error.kind, ApiErrorKind::InvalidRequest);
+    assert_eq!(error.error.message, "max_tokens: Field required");
+    assert!(matches!(results[2].result, BatchResultKind::Expired));
+}
+
+// Reports a batch as in progress on the first poll, and as ended afterwards
+struct PollResponder {
+    polls: Arc<AtomicUsize>,
+    batch: Value,
+}
+
+impl wiremock::Respond for PollResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let id = request.url.path().rsplit('/').next().unwrap();
+        let status = if self.polls.fetch_add(1, Ordering::SeqCst) == 0 {
+            "in_progress"
+        } else {
+            "ended"
+        };
+
+        let mut batch = self.batch.clone();
+        batch["id"] = id.into();
+        batch["processing_status"] = status.into();
+        ResponseTemplate::new(200).set_body_json(batch)
+    }
+}
+
+// Creates batches with increasing ids
+struct CreateResponder {
+    created: Arc<AtomicUsize>,
+    batch: Value,
+}
+
+impl wiremock::Respond for CreateResponder {
+    fn respond(&self, _: &wiremock::Request) -> ResponseTemplate {
+        let mut batch = self.batch.clone();
+        batch["id"] = format!("msgbatch_{}", self.created.fetch_add(1, Ordering::SeqCst)).into();
+        ResponseTemplate::new(200).set_body_json(batch)
+    }
+}
+
+fn succeeded(custom_id: &str, text: &str) -> String {
+    json!({"custom_id": custom_id, "result": {"type": "succeeded", "message": {
+        "content": [{"type": "text", "text": text}]
+    }}})
+    .to_string()
+}
+
+#[test_log::test(tokio::test)]
+async fn test_batch_job_splits_polls_and_maps_results() {
+    let server = TestSetup::setup().await;
+    let batch = json!({
+        "id": "msgbatch_1",
+        "type": "message_batch",
+        "processing_status": "in_progress",
+        "request_counts": {"processing": 1, "succeeded": 0, "errored": 0, "canceled": 0, "expired": 0},
+        "created_at": "2024-09-24T18:37:24.100435Z",
+        "expires_at": "2024-09-25T18:37:24.100435Z",
+        "ended_at": null,
+        "archived_at": null,
+        "cancel_initiated_at": null,
+        "results_url": null
+    });
+    let created = Arc::new(AtomicUsize::new(0));
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches"))
+        .respond_with(CreateResponder {
+            created: created.clone(),
+            batch: batch.clone(),
+        })
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/v1/messages/batches/msgbatch_\d+$"))
+        .respond_with(PollResponder {
+            polls: Arc::new(AtomicUsize::new(0)),
+            batch,
+        })
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches/msgbatch_0/results"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            [succeeded("request-1", "two"), succeeded("request-0", "one")].join("\n"),
+        ))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/messages/batches/msgbatch_1/results"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(succeeded("request-2", "three")))
+        .mount(&server)
+        .await;
+
+    let requests = ["a", "b", "c"].map(|id| {
+        let params = CreateMessagesRequestBuilder::default()
+            .model("test-model")
+            .messages(vec![id.into()])
+            .build()
+            .unwrap();
+        (id.to_string(), params)
+    });
+
+    let poll_backoff = ExponentialBackoffBuilder::default()
+        .with_initial_interval(Duration::from_millis(1))
+        .with_max_elapsed_time(None)
+        .build();
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+    let results = BatchJob::new(&client)
+        .with_max_batch_size(2)
+        .with_poll_backoff(poll_backoff)
+        .run(requests)
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .map(|result| {
+            let (id, result) = result.unwrap();
+            let BatchResultKind::Succeeded { message } = result else {
+                panic!("unexpected result: {result:?}");
+            };
+            (id, message.message().text().unwrap())
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        results,
+        vec![
+            ("b".to_string(), "two".to_string()),
+            ("a".to_string(), "one".to_string()),
+            ("c".to_string(), "three".to_string()),
+        ]
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_batch_job_reports_created_batches_on_failure() {
+    let server = TestSetup::setup().await;
+    let batch = json!({
+        "id": "msgbatch_1",
+        "type": "message_batch",
+        "processing_status": "in_progress",
+        "request_counts": {"processing": 1, "succeeded": 0, "errored": 0, "canceled": 0, "expired": 0},
+        "created_at": "2024-09-24T18:37:24.100435Z",
+        "expires_at": "2024-09-25T18:37:24.100435Z",
+        "ended_at": null,
+        "archived_at": null,
+        "cancel_initiated_at": null,
+        "results_url": null
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(batch))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/batches"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Too many batches"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let requests = ["a", "b"].map(|id| {
+        let params = CreateMessagesRequestBuilder::default()
+            .model("test-model")
+            .messages(vec![id.into()])
+            .build()
+            .unwrap();
+        (id.to_string(), params)
+    });
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+    let result = BatchJob::new(&client)
+        .with_max_batch_size(1)
+        .submit(requests)
+        .await;
+
+    let Err(AnthropicError::PartialBatchSubmission { batch_ids, source }) = result else {
+        panic!("unexpected result: {result:?}");
+    };
+    assert_eq!(batch_ids, vec!["msgbatch_1"]);
+    assert_eq!(source.kind(), Some(ApiErrorKind::InvalidRequest));
+}
@@ -0,0 +1,222 @@
+use async_anthropic::{
+    errors::AnthropicError,
+    files::FILES_API_BETA,
+    types::{Document, ListParamsBuilder},
+    Client,
+};
+use async_trait::async_trait;
+use serde_json::json;
+use wiremock::{
+    matchers::{header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[async_trait]
+pub trait MockApp {
+    async fn setup() -> MockServer;
+}
+
+struct TestSetup;
+
+#[async_trait]
+impl MockApp for TestSetup {
+    async fn setup() -> MockServer {
+        MockServer::start().await
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_upload_file() {
+    let server = TestSetup::setup().await;
+
+    let file = json!({
+        "id": "file_1",
+        "type": "file",
+        "filename": "report.pdf",
+        "mime_type": "application/pdf",
+        "size_bytes": 4,
+        "created_at": "2025-04-14T12:00:00Z",
+        "downloadable": false
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/v1/files"))
+        .and(header("anthropic-beta", FILES_API_BETA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(file))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let file = client
+        .files()
+        .upload("report.pdf", "application/pdf", b"%PDF".to_vec())
+        .await
+        .unwrap();
+
+    assert_eq!(file.id, "file_1");
+    assert_eq!(file.size_bytes, 4);
+
+    let request = &server.received_requests().await.unwrap()[0];
+    let content_type = request.headers["content-type"].to_str().unwrap();
+    assert!(content_type.starts_with("multipart/form-data"));
+
+    let body = String::from_utf8_lossy(&request.body);
+    assert!(body.contains(r#"name="file"; filename="report.pdf""#));
+    assert!(body.contains("%PDF"));
+}
+
+#[test_log::test(tokio::test)]
+async fn test_list_get_download_and_delete_files() {
+    let server = TestSetup::setup().await;
+
+    let file = json!({
+        "id": "file_1",
+        "type": "file",
+        "filename": "report.pdf",
+        "mime_type": "application/pdf",
+        "size_bytes": 4,
+        "created_at": "2025-04-14T12:00:00Z",
+        "downloadable": false
+    });
+
+    Mock::given(method("GET"))
+        .and(path("/v1/files"))
+        .and(query_param("after_id", "file_0"))
+        .and(query_param("limit", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [file.clone()],
+            "has_more": true,
+            "first_id": "file_1",
+            "last_id": "file_1"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/files/file_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(file))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/files/file_1/content"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"%PDF".to_vec()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("DELETE"))
+        .and(path("/v1/files/file_1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "file_1",
+            "type": "file_deleted"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+    let params = ListParamsBuilder::default()
+        .after_id("file_0")
+        .limit(1u32)
+        .build()
+        .unwrap();
+
+    let list = client.files().list_page(&params).await.unwrap();
+    assert!(list.has_more);
+    assert_eq!(list.data[0].filename, "report.pdf");
+
+    let file = client.files().get("file_1").await.unwrap();
+    assert_eq!(file.mime_type, "application/pdf");
+
+    let content = client.files().download("file_1").await.unwrap();
+    assert_eq!(content, b"%PDF");
+
+    let deleted = client.files().delete("file_1").await.unwrap();
+    assert_eq!(deleted.id, "file_1");
+}
+
+#[tokio::test]
+async fn test_upload_rejects_invalid_mime_type() {
+    let server = TestSetup::setup().await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let result = client
+        .files()
+        .upload("report.pdf", "not a mime type", b"%PDF".to_vec())
+        .await;
+
+    assert!(
+        matches!(result, Err(AnthropicError::BadRequest(_))),
+        "actual: {result:?}"
+    );
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[test]
+fn test_file_id_sources() {
+    let document = serde_json::to_value(Document::from_file_id("file_1")).unwrap();
+    assert_eq!(
+        document["source"],
+        json!({"type": "file", "file_id": "file_1"})
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn test_upload_path() {
+    let server = TestSetup::setup().await;
+
+    let file = json!({
+        "id": "file_1",
+        "type": "file",
+        "filename": "report.pdf",
+        "mime_type": "application/pdf",
+        "size_bytes": 4,
+        "created_at": "2025-04-14T12:00:00Z",
+        "downloadable": false
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/v1/files"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(file))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let dir = std::env::temp_dir().join(format!("async-anthropic-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.pdf");
+    std::fs::write(&path, b"%PDF").unwrap();
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let result = client.files().upload_path(&path).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(result.unwrap().id, "file_1");
+
+    let request = &server.received_requests().await.unwrap()[0];
+    let body = String::from_utf8_lossy(&request.body);
+    assert!(body.contains(r#"filename="report.pdf""#));
+    assert!(body.contains("Content-Type: application/pdf"));
+}
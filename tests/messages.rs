@@ -1,6 +1,10 @@
 use async_anthropic::{
-    errors::AnthropicError,
-    types::{CreateMessagesRequestBuilder, MessageBuilder, MessageContent, MessageRole},
+    errors::{AnthropicError, ApiErrorKind},
+    retry::{DefaultRetryPolicy, ErrorClass, RetryBackoff},
+    stream::accumulate,
+    types::{
+        CreateMessagesRequestBuilder, MessageBuilder, MessageContent, MessageRole, StopReason,
+    },
     Client,
 };
 use async_trait::async_trait;
@@ -8,7 +12,7 @@ use backoff::ExponentialBackoffBuilder;
 use serde_json::json;
 use std::{sync::Arc, sync::Mutex, time::Duration};
 use wiremock::{
-    matchers::{method, path},
+    matchers::{body_json, method, path},
     Mock, MockServer, ResponseTemplate,
 };
 
@@ -224,7 +228,14 @@ async fn test_error_handling_bad_request() {
     // Mock 400 Bad Request response
     Mock::given(method("POST"))
         .and(path("/v1/messages"))
-        .respond_with(ResponseTemplate::new(400).set_body_string("Bad request"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .insert_header("request-id", "req_1")
+                .set_body_json(json!({
+                    "type": "error",
+                    "error": {"type": "invalid_request_error", "message": "max_tokens: Field required"}
+                })),
+        )
         .expect(1)
         .mount(&server)
         .await;
@@ -250,10 +261,15 @@ async fn test_error_handling_bad_request() {
 
     assert!(result.is_err());
     assert!(
-        matches!(result.as_ref().unwrap_err(), AnthropicError::BadRequest(_)),
+        result.as_ref().unwrap_err().kind() == Some(ApiErrorKind::InvalidRequest),
         "actual: {:?}",
         &result
-    )
+    );
+
+    let error = result.unwrap_err();
+    assert_eq!(error.status(), Some(400));
+    assert_eq!(error.request_id(), Some("req_1"));
+    assert!(error.to_string().contains("max_tokens: Field required"));
 }
 
 #[tokio::test]
@@ -290,8 +306,640 @@ async fn test_error_handling_unauthorized() {
 
     assert!(result.is_err());
     assert!(
-        matches!(result.as_ref().unwrap_err(), AnthropicError::Unauthorized),
+        result.as_ref().unwrap_err().kind() == Some(ApiErrorKind::Authentication),
         "actual: {:?}",
         &result
     )
 }
+
+#[tokio::test]
+async fn test_count_tokens() {
+    let server = TestSetup::setup().await;
+    let secret_key = "test_secret";
+
+    // Generation parameters like max_tokens must not be sent
+    Mock::given(method("POST"))
+        .and(path("/v1/messages/count_tokens"))
+        .and(body_json(json!({
+            "model": "test-model",
+            "system": "Be brief",
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "Hello world!"}]}]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"input_tokens": 14})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key(secret_key)
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model".to_string())
+        .system("Be brief")
+        .messages(vec![MessageBuilder::default()
+            .role(MessageRole::User)
+            .content("Hello world!")
+            .build()
+            .unwrap()])
+        .build()
+        .unwrap();
+
+    let result = client.messages().count_tokens(request).await.unwrap();
+
+    assert_eq!(result.input_tokens, 14);
+}
+
+#[tokio::test]
+async fn test_retries_server_errors_after_retry_after() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(503)
+                .insert_header("retry-after", "0")
+                .set_body_string("Service Unavailable"),
+        )
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{"type": "text", "text": "retried response"}]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // Without the retry-after header, the first retry would wait an hour
+    let backoff = ExponentialBackoffBuilder::default()
+        .with_initial_interval(Duration::from_secs(3600))
+        .with_max_elapsed_time(None)
+        .build();
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap()
+        .with_backoff(backoff);
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(5), client.messages().create(request))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        response.message().text(),
+        Some("retried response".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_retry_policy_retries_connection_errors() {
+    // Reserve a port and close it again, so connections are refused
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let retries = Arc::new(Mutex::new(vec![]));
+    let recorded = retries.clone();
+
+    let policy = DefaultRetryPolicy::default()
+        .with_max_attempts(3)
+        .with_backoff(
+            ErrorClass::Connection,
+            RetryBackoff::new(Duration::from_millis(1), Duration::from_millis(10)),
+        )
+        .with_on_retry(move |context, _| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((context.attempt, context.class()));
+        });
+
+    let client = Client::builder()
+        .base_url(base_url)
+        .api_key("test_secret")
+        .retry_policy(policy)
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let result = client.messages().create(request).await;
+
+    assert!(
+        matches!(result, Err(AnthropicError::NetworkError(_))),
+        "actual: {result:?}"
+    );
+    assert_eq!(
+        *retries.lock().unwrap(),
+        vec![(1, ErrorClass::Connection), (2, ErrorClass::Connection)]
+    );
+}
+
+fn sse_body(events: &[serde_json::Value]) -> String {
+    events
+        .iter()
+        .map(|event| {
+            format!(
+                "event: {}\ndata: {event}\n\n",
+                event["type"].as_str().unwrap()
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_stream_retries_before_first_event() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(529)
+                .insert_header("retry-after", "0")
+                .set_body_json(json!({
+                    "type": "error",
+                    "error": {"type": "overloaded_error", "message": "Overloaded"}
+                })),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let body = sse_body(&[
+        json!({"type": "message_start", "message": {"id": "msg_1", "model": "test-model", "role": "assistant", "content": []}}),
+        json!({"type": "ping"}),
+        json!({"type": "some_new_event", "data": {}}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello!"}}),
+        json!({"type": "content_block_stop", "index": 0}),
+        json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}}),
+        json!({"type": "message_stop"}),
+    ]);
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_string(body),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let stream = client.messages().create_stream(request).await;
+    let response = accumulate(stream).await.unwrap();
+
+    assert_eq!(response.message().text(), Some("Hello!".to_string()));
+}
+
+#[tokio::test]
+async fn test_stream_retries_error_event_before_first_event() {
+    let server = TestSetup::setup().await;
+
+    let overloaded = sse_body(&[
+        json!({"type": "ping"}),
+        json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+    ]);
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_string(overloaded),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let body = sse_body(&[
+        json!({"type": "message_start", "message": {"id": "msg_1", "model": "test-model", "role": "assistant", "content": []}}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello!"}}),
+        json!({"type": "content_block_stop", "index": 0}),
+        json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}}),
+        json!({"type": "message_stop"}),
+    ]);
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_string(body),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let retries = Arc::new(Mutex::new(vec![]));
+    let recorded = retries.clone();
+
+    let policy = DefaultRetryPolicy::default()
+        .with_backoff(
+            ErrorClass::Overloaded,
+            RetryBackoff::new(Duration::from_millis(1), Duration::from_millis(10)),
+        )
+        .with_on_retry(move |context, _| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((context.attempt, context.class()));
+        });
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .retry_policy(policy)
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let stream = client.messages().create_stream(request).await;
+    let response = accumulate(stream).await.unwrap();
+
+    assert_eq!(response.message().text(), Some("Hello!".to_string()));
+    assert_eq!(*retries.lock().unwrap(), vec![(1, ErrorClass::Overloaded)]);
+}
+
+// Reads a request up to the end of its body
+async fn read_request(socket: &mut tokio::net::TcpStream) {
+    use tokio::io::AsyncReadExt as _;
+
+    let mut request = vec![];
+    let mut buffer = [0; 1024];
+
+    loop {
+        let read = socket.read(&mut buffer).await.unwrap();
+        request.extend_from_slice(&buffer[..read]);
+
+        let text = String::from_utf8_lossy(&request).to_lowercase();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .map_or(0, |length| length.trim().parse::<usize>().unwrap());
+            if request.len() >= end + 4 + length {
+                return;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_stream_retries_connection_errors_before_first_event() {
+    use tokio::io::AsyncWriteExt as _;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let body = sse_body(&[
+        json!({"type": "message_start", "message": {"id": "msg_1", "model": "test-model", "role": "assistant", "content": []}}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello!"}}),
+        json!({"type": "content_block_stop", "index": 0}),
+        json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}}),
+        json!({"type": "message_stop"}),
+    ]);
+
+    tokio::spawn(async move {
+        // The connection is closed after a ping, before the first event
+        let (mut socket, _) = listener.accept().await.unwrap();
+        read_request(&mut socket).await;
+        let ping = sse_body(&[json!({"type": "ping"})]);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n{:x}\r\n{ping}\r\n",
+            ping.len()
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        drop(socket);
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        read_request(&mut socket).await;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let retries = Arc::new(Mutex::new(vec![]));
+    let recorded = retries.clone();
+
+    let policy = DefaultRetryPolicy::default()
+        .with_backoff(
+            ErrorClass::Connection,
+            RetryBackoff::new(Duration::from_millis(1), Duration::from_millis(10)),
+        )
+        .with_on_retry(move |context, _| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((context.attempt, context.class()));
+        });
+
+    let client = Client::builder()
+        .base_url(base_url)
+        .api_key("test_secret")
+        .retry_policy(policy)
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let stream = client.messages().create_stream(request).await;
+    let response = accumulate(stream).await.unwrap();
+
+    assert_eq!(response.message().text(), Some("Hello!".to_string()));
+    assert_eq!(*retries.lock().unwrap(), vec![(1, ErrorClass::Connection)]);
+}
+
+#[tokio::test]
+async fn test_stream_maps_error_status() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "type": "error",
+            "error": {"type": "invalid_request_error", "message": "max_tokens: Field required"}
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let stream = client.messages().create_stream(request).await;
+    let error = accumulate(stream).await.unwrap_err();
+
+    assert_eq!(error.kind(), Some(ApiErrorKind::InvalidRequest));
+    assert_eq!(error.status(), Some(400));
+}
+
+#[tokio::test]
+async fn test_stream_maps_error_events() {
+    let server = TestSetup::setup().await;
+
+    let body = sse_body(&[
+        json!({"type": "message_start", "message": {"id": "msg_1", "model": "test-model", "role": "assistant", "content": []}}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+        json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+    ]);
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .insert_header("request-id", "req_1")
+                .set_body_string(body),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Hello world!".into()])
+        .build()
+        .unwrap();
+
+    let stream = client.messages().create_stream(request).await;
+    let error = accumulate(stream).await.unwrap_err();
+
+    assert_eq!(error.kind(), Some(ApiErrorKind::Overloaded));
+    assert_eq!(error.status(), Some(200));
+    assert_eq!(error.request_id(), Some("req_1"));
+    assert!(error.to_string().contains("Overloaded"));
+}
+
+// Cuts the response off until the prefill is long enough
+struct ContinuationResponder;
+
+impl wiremock::Respond for ContinuationResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let last = body["messages"].as_array().unwrap().last().unwrap();
+
+        let (text, stop_reason) = if last["role"] == "user" {
+            ("Once upon a time, ", "max_tokens")
+        } else {
+            assert_eq!(last["content"][0]["text"], "Once upon a time,");
+            (" the end.", "end_turn")
+        };
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{"type": "text", "text": text}],
+            "stop_reason": stop_reason,
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }))
+    }
+}
+
+#[tokio::test]
+async fn test_create_with_continuation() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ContinuationResponder)
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Tell me a story".into()])
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .with_continuation(100)
+        .create(request)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.message().text(),
+        Some("Once upon a time, the end.".to_string())
+    );
+    assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+
+    let usage = response.usage.unwrap();
+    assert_eq!(usage.input_tokens, Some(20));
+    assert_eq!(usage.output_tokens, Some(10));
+}
+
+// Cuts the response off after a whitespace block, and continues it with two blocks
+struct BlockContinuationResponder;
+
+impl wiremock::Respond for BlockContinuationResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let last = body["messages"].as_array().unwrap().last().unwrap();
+
+        let (content, stop_reason) = if last["role"] == "user" {
+            (
+                json!([{"type": "text", "text": "First block."}, {"type": "text", "text": "\n\n"}]),
+                "max_tokens",
+            )
+        } else {
+            assert_eq!(
+                last["content"],
+                json!([{"type": "text", "text": "First block."}])
+            );
+            (
+                json!([{"type": "text", "text": " Continued."}, {"type": "text", "text": "Second block."}]),
+                "end_turn",
+            )
+        };
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "content": content,
+            "stop_reason": stop_reason,
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }))
+    }
+}
+
+#[tokio::test]
+async fn test_continuation_drops_empty_blocks_and_keeps_boundaries() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(BlockContinuationResponder)
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Tell me a story".into()])
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .with_continuation(100)
+        .create(request)
+        .await
+        .unwrap();
+
+    let texts = response
+        .content
+        .unwrap()
+        .iter()
+        .map(|block| block.as_text().unwrap().text.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["First block. Continued.", "Second block."]);
+}
+
+#[tokio::test]
+async fn test_continuation_stops_at_token_cap() {
+    let server = TestSetup::setup().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ContinuationResponder)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .base_url(server.uri())
+        .api_key("test_secret")
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Tell me a story".into()])
+        .build()
+        .unwrap();
+
+    let response = client
+        .messages()
+        .with_continuation(5)
+        .create(request)
+        .await
+        .unwrap();
+
+    assert_eq!(response.stop_reason, Some(StopReason::MaxTokens));
+    assert!(response.stop_reason.unwrap().was_truncated());
+}
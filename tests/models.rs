@@ -1,11 +1,12 @@
 use async_anthropic::{
-    errors::AnthropicError,
-    types::{GetModelResponse, ListModelsResponse},
+    errors::ApiErrorKind,
+    types::{GetModelResponse, ListModelsResponse, ListParams, ListParamsBuilder, Model},
     Client,
 };
 use async_trait::async_trait;
+use tokio_stream::StreamExt as _;
 use wiremock::{
-    matchers::{method, path},
+    matchers::{method, path, query_param, query_param_is_missing},
     Mock, MockServer, ResponseTemplate,
 };
 
@@ -104,7 +105,7 @@ async fn test_error_handling_bad_request() {
 
     assert!(result.is_err());
     assert!(
-        matches!(result.as_ref().unwrap_err(), AnthropicError::BadRequest(_)),
+        result.as_ref().unwrap_err().kind() == Some(ApiErrorKind::InvalidRequest),
         "actual: {:?}",
         &result
     );
@@ -133,8 +134,127 @@ async fn test_error_handling_unauthorized() {
 
     assert!(result.is_err());
     assert!(
-        matches!(result.as_ref().unwrap_err(), AnthropicError::Unauthorized),
+        result.as_ref().unwrap_err().kind() == Some(ApiErrorKind::Authentication),
         "actual: {:?}",
         &result
     );
 }
+
+#[tokio::test]
+async fn test_list_all_models_follows_cursors() {
+    let server = TestSetup::setup().await;
+
+    let model = |id: &str| Model {
+        created_at: "2023-10-10T00:00:00Z".to_string(),
+        display_name: id.to_string(),
+        id: id.to_string(),
+        model_type: "model".to_string(),
+    };
+
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .and(query_param("after_id", "model_2"))
+        .and(query_param("limit", "2"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&ListModelsResponse {
+                data: vec![model("model_3")],
+                first_id: Some("model_3".to_string()),
+                has_more: false,
+                last_id: Some("model_3".to_string()),
+            }),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .and(query_param_is_missing("after_id"))
+        .and(query_param("limit", "2"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&ListModelsResponse {
+                data: vec![model("model_1"), model("model_2")],
+                first_id: Some("model_1".to_string()),
+                has_more: true,
+                last_id: Some("model_2".to_string()),
+            }),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let ids = client
+        .models()
+        .list_all(ListParamsBuilder::default().limit(2u32).build().unwrap())
+        .map(|model| model.unwrap().id)
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(ids, vec!["model_1", "model_2", "model_3"]);
+}
+
+#[tokio::test]
+async fn test_list_all_models_pages_backwards_from_before_id() {
+    let server = TestSetup::setup().await;
+
+    let model = |id: &str| Model {
+        created_at: "2023-10-10T00:00:00Z".to_string(),
+        display_name: id.to_string(),
+        id: id.to_string(),
+        model_type: "model".to_string(),
+    };
+
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .and(query_param("before_id", "model_4"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&ListModelsResponse {
+                data: vec![model("model_2"), model("model_3")],
+                first_id: Some("model_2".to_string()),
+                has_more: true,
+                last_id: Some("model_3".to_string()),
+            }),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/v1/models"))
+        .and(query_param("before_id", "model_2"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&ListModelsResponse {
+                data: vec![model("model_1")],
+                first_id: Some("model_1".to_string()),
+                has_more: false,
+                last_id: Some("model_1".to_string()),
+            }),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let ids = client
+        .models()
+        .list_all(ListParams {
+            before_id: Some("model_4".to_string()),
+            ..Default::default()
+        })
+        .map(|model| model.unwrap().id)
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(ids, vec!["model_2", "model_3", "model_1"]);
+}
@@ -0,0 +1,328 @@
+use async_anthropic::{
+    tool_runner::ToolRunner,
+    types::{CreateMessagesRequestBuilder, MessageContent, MessageRole},
+    Client,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Barrier;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+// Requests tools until the last message contains tool results
+struct ToolUseResponder;
+
+impl wiremock::Respond for ToolUseResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let last = body["messages"].as_array().unwrap().last().unwrap().clone();
+
+        if last["content"][0]["type"] == "tool_result" {
+            ResponseTemplate::new(200).set_body_json(json!({
+                "content": [{"type": "text", "text": "done"}],
+                "stop_reason": "end_turn"
+            }))
+        } else {
+            ResponseTemplate::new(200).set_body_json(json!({
+                "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"location": "Amsterdam"}},
+                    {"type": "tool_use", "id": "toolu_2", "name": "missing", "input": {}}
+                ],
+                "stop_reason": "tool_use"
+            }))
+        }
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_runner_executes_tools() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ToolUseResponder)
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["What is the weather in Amsterdam?".into()])
+        .build()
+        .unwrap();
+
+    let output = ToolRunner::new(&client)
+        .with_tool("get_weather", |tool_use| async move {
+            Ok::<_, String>(format!("Rainy in {}", tool_use.input["location"]))
+        })
+        .run(request)
+        .await
+        .unwrap();
+
+    assert_eq!(output.iterations, 2);
+    assert!(!output.max_iterations_reached);
+    assert_eq!(output.response.message().text(), Some("done".to_string()));
+    assert_eq!(output.messages.len(), 4);
+
+    let results = &output.messages[2];
+    assert_eq!(results.role, MessageRole::User);
+
+    let results = results
+        .content
+        .iter()
+        .filter_map(MessageContent::as_tool_result)
+        .collect::<Vec<_>>();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].tool_use_id, "toolu_1");
+    assert_eq!(
+        results[0].content.as_deref(),
+        Some("Rainy in \"Amsterdam\"")
+    );
+    assert!(!results[0].is_error);
+    assert_eq!(results[1].tool_use_id, "toolu_2");
+    assert!(results[1].is_error);
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_runner_max_iterations() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{"type": "tool_use", "id": "toolu_1", "name": "loop", "input": {}}],
+            "stop_reason": "tool_use"
+        })))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Loop forever".into()])
+        .build()
+        .unwrap();
+
+    let output = ToolRunner::new(&client)
+        .with_tool("loop", |_| async { Ok::<_, String>("again".to_string()) })
+        .with_max_iterations(3)
+        .run(request)
+        .await
+        .unwrap();
+
+    assert_eq!(output.iterations, 3);
+    assert!(output.max_iterations_reached);
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_runner_parallel_execution() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ToolUseResponder)
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["What is the weather in Amsterdam?".into()])
+        .build()
+        .unwrap();
+
+    let output = ToolRunner::new(&client)
+        .with_tool("get_weather", |_| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<_, String>("Sunny".to_string())
+        })
+        .with_tool("missing", |_| async {
+            Ok::<_, String>("found".to_string())
+        })
+        .with_max_parallelism(2)
+        .with_tool_timeout(Duration::from_millis(50))
+        .run(request)
+        .await
+        .unwrap();
+
+    let results = output.messages[2]
+        .content
+        .iter()
+        .filter_map(MessageContent::as_tool_result)
+        .collect::<Vec<_>>();
+
+    // Order is preserved even though the second tool finishes first
+    assert_eq!(results[0].tool_use_id, "toolu_1");
+    assert!(results[0].is_error);
+    assert_eq!(results[1].tool_use_id, "toolu_2");
+    assert_eq!(results[1].content.as_deref(), Some("found"));
+    assert!(!results[1].is_error);
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_runner_runs_tools_concurrently() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ToolUseResponder)
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["What is the weather in Amsterdam?".into()])
+        .build()
+        .unwrap();
+
+    // Neither handler can finish before both have started, so sequential execution never completes
+    let barrier = Arc::new(Barrier::new(2));
+    let weather_barrier = barrier.clone();
+
+    let runner = ToolRunner::new(&client)
+        .with_tool("get_weather", move |_| {
+            let barrier = weather_barrier.clone();
+            async move {
+                barrier.wait().await;
+                Ok::<_, String>("Sunny".to_string())
+            }
+        })
+        .with_tool("missing", move |_| {
+            let barrier = barrier.clone();
+            async move {
+                barrier.wait().await;
+                Ok::<_, String>("found".to_string())
+            }
+        })
+        .with_max_parallelism(2);
+
+    let output = tokio::time::timeout(Duration::from_secs(5), runner.run(request))
+        .await
+        .expect("tools did not run concurrently")
+        .unwrap();
+
+    let results = output.messages[2]
+        .content
+        .iter()
+        .filter_map(MessageContent::as_tool_result)
+        .collect::<Vec<_>>();
+
+    assert_eq!(results[0].content.as_deref(), Some("Sunny"));
+    assert_eq!(results[1].content.as_deref(), Some("found"));
+}
+
+// Requests a number of tool uses at once
+struct ManyToolUsesResponder {
+    tool_uses: usize,
+}
+
+impl wiremock::Respond for ManyToolUsesResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let last = body["messages"].as_array().unwrap().last().unwrap().clone();
+
+        if last["content"][0]["type"] == "tool_result" {
+            return ResponseTemplate::new(200).set_body_json(json!({
+                "content": [{"type": "text", "text": "done"}],
+                "stop_reason": "end_turn"
+            }));
+        }
+
+        let tool_uses = (0..self.tool_uses)
+            .map(|i| json!({"type": "tool_use", "id": format!("toolu_{i}"), "name": "work", "input": {}}))
+            .collect::<Vec<_>>();
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "content": tool_uses,
+            "stop_reason": "tool_use"
+        }))
+    }
+}
+
+#[test_log::test(tokio::test)]
+async fn test_tool_runner_respects_max_parallelism() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .respond_with(ManyToolUsesResponder { tool_uses: 6 })
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = Client::builder()
+        .api_key("test_secret")
+        .base_url(server.uri())
+        .build()
+        .unwrap();
+
+    let request = CreateMessagesRequestBuilder::default()
+        .model("test-model")
+        .messages(vec!["Do some work".into()])
+        .build()
+        .unwrap();
+
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (handler_in_flight, handler_peak) = (in_flight.clone(), peak.clone());
+
+    let output = ToolRunner::new(&client)
+        .with_tool("work", move |_| {
+            let (in_flight, peak) = (handler_in_flight.clone(), handler_peak.clone());
+            async move {
+                let running = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok::<_, String>("worked".to_string())
+            }
+        })
+        .with_max_parallelism(3)
+        .run(request)
+        .await
+        .unwrap();
+
+    assert_eq!(peak.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        output.messages[2]
+            .content
+            .iter()
+            .filter_map(MessageContent::as_tool_result)
+            .filter(|result| !result.is_error)
+            .count(),
+        6
+    );
+}